@@ -1,17 +1,13 @@
-use sp_core::{Pair, Public, sr25519};
+use sp_core::{Pair, Public, sr25519, hashing::blake2_256};
 use node_template_runtime::{
 	AccountId, AuraConfig, BalancesConfig, GenesisConfig, GrandpaConfig,
-	SudoConfig, SystemConfig, WASM_BINARY, Signature
+	SudoConfig, SystemConfig, WASM_BINARY, Signature, Runtime, token,
 };
 use sp_consensus_aura::sr25519::{AuthorityId as AuraId};
 use grandpa_primitives::{AuthorityId as GrandpaId};
 use sc_service;
 use sp_runtime::traits::{Verify, IdentifyAccount};
 
-use runtime::{
-    TokenConfig
-};
-
 // Note this is the URL for the telemetry server
 //const STAGING_TELEMETRY_URL: &str = "wss://telemetry.polkadot.io/submit/";
 
@@ -45,6 +41,40 @@ pub fn get_account_id_from_seed<TPublic: Public>(seed: &str) -> AccountId where
 	AccountPublic::from(get_from_seed::<TPublic>(seed)).into_account()
 }
 
+/// The testing prime, the largest safe prime below 2^31: 7 and 48271 are
+/// squares and generate its subgroup of prime order (TOKEN_PRIME - 1) / 2
+const TOKEN_PRIME: u128 = 2147483579;
+const TOKEN_GENERATOR: u128 = 7;
+const TOKEN_SECOND_GENERATOR: u128 = 48271;
+/// The second token uses the same prime, 7^5 and 69620 generate the subgroup too
+const SECOND_TOKEN_GENERATOR: u128 = 16807;
+const SECOND_TOKEN_SECOND_GENERATOR: u128 = 69620;
+const TOKEN_RANGE_BITS: u32 = 16;
+
+/// Helper function to derive the confidential secret key of a development
+/// account from its seed. Anyone can recompute it, it is for testing only.
+pub fn get_token_secret_from_seed(seed: &str) -> u128 {
+	let hash = blake2_256(format!("//{}//token", seed).as_bytes());
+	let mut bytes = [0u8; 16];
+	bytes.copy_from_slice(&hash[..16]);
+	u128::from_le_bytes(bytes) % ((TOKEN_PRIME - 1) / 2)
+}
+
+/// Helper function to generate a confidential genesis account holding value,
+/// encrypted with the token generator
+pub fn get_token_account_from_seed(generator: u128, seed: &str, value: u128)
+	-> (AccountId, token::BigInt, (token::BigInt, token::BigInt))
+{
+	let cipher = token::EGICipher { gamma: generator, prime: TOKEN_PRIME };
+	let rand = get_token_secret_from_seed(&format!("{}//rand", seed));
+	let (pubkey, current) = token::prover::genesis_account(&cipher, get_token_secret_from_seed(seed), value, rand);
+	(
+		get_account_id_from_seed::<sr25519::Public>(seed),
+		pubkey.into(),
+		(current.0.into(), current.1.into()),
+	)
+}
+
 /// Helper function to generate an authority key for Aura
 pub fn get_authority_keys_from_seed(s: &str) -> (AuraId, GrandpaId) {
 	(
@@ -53,6 +83,20 @@ pub fn get_authority_keys_from_seed(s: &str) -> (AuraId, GrandpaId) {
 	)
 }
 
+/// Helper function to generate the genesis of a token instance
+fn token_genesis<I: token::Instance>(generator: u128, second_generator: u128, balances: &[(&str, u128)])
+	-> token::GenesisConfig<Runtime, I>
+{
+	token::GenesisConfig {
+		primeset: TOKEN_PRIME.into(),
+		generator: generator.into(),
+		second_generator: second_generator.into(),
+		range_bits: TOKEN_RANGE_BITS,
+		accounts: balances.iter().map(|&(seed, value)| get_token_account_from_seed(generator, seed, value)).collect(),
+		issuance: balances.iter().map(|&(_, value)| value).sum::<u128>().into(),
+	}
+}
+
 impl Alternative {
 	/// Get an actual chain config from one of the alternatives.
 	pub(crate) fn load(self) -> Result<ChainSpec, String> {
@@ -126,6 +170,8 @@ fn testnet_genesis(initial_authorities: Vec<(AuraId, GrandpaId)>,
 	root_key: AccountId,
 	endowed_accounts: Vec<AccountId>,
 	_enable_println: bool) -> GenesisConfig {
+	let token_balances = [("Alice", 1000), ("Bob", 1000)];
+	let second_token_balances = [("Alice", 500), ("Charlie", 500)];
 	GenesisConfig {
 		system: Some(SystemConfig {
 			code: WASM_BINARY.to_vec(),
@@ -138,9 +184,8 @@ fn testnet_genesis(initial_authorities: Vec<(AuraId, GrandpaId)>,
 			authorities: initial_authorities.iter().map(|x| (x.0.clone())).collect(),
 		}),
 
-        token: Some(TokenConfig {
-            primeset: 2147483647 //2^31 - 1 is testing prime
-        }),
+		token: Some(token_genesis(TOKEN_GENERATOR, TOKEN_SECOND_GENERATOR, &token_balances)),
+		token_Instance1: Some(token_genesis(SECOND_TOKEN_GENERATOR, SECOND_TOKEN_SECOND_GENERATOR, &second_token_balances)),
 
 		grandpa: Some(GrandpaConfig {
 			authorities: initial_authorities.iter().map(|x| (x.1.clone(), 1)).collect(),
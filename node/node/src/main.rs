@@ -4,6 +4,8 @@
 mod chain_spec;
 #[macro_use]
 mod service;
+mod pool;
+mod rpc;
 mod cli;
 mod command;
 
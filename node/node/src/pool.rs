@@ -0,0 +1,210 @@
+//! Transaction pool chain API remembering which extrinsics were found valid.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use futures::{future::{self, FutureExt}, Future};
+use sc_transaction_pool::txpool::{ChainApi, ExtrinsicFor, BlockHash, NumberFor};
+use sp_runtime::{generic::BlockId, transaction_validity::TransactionValidity};
+
+/// Most validation results kept, the least recently used goes first.
+const CACHE_SIZE: usize = 4096;
+
+/// Values by key, dropping the least recently used once `capacity` is reached.
+struct Lru<K, V> {
+	capacity: usize,
+	/// Value and last use of every key.
+	entries: HashMap<K, (V, u64)>,
+	/// Keys by last use.
+	uses: BTreeMap<u64, K>,
+	clock: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Lru<K, V> {
+	fn new(capacity: usize) -> Self {
+		Lru { capacity, entries: HashMap::new(), uses: BTreeMap::new(), clock: 0 }
+	}
+
+	fn tick(&mut self) -> u64 {
+		self.clock += 1;
+		self.clock
+	}
+
+	fn get(&mut self, key: &K) -> Option<V> {
+		let now = self.tick();
+		let (value, used) = self.entries.get_mut(key)?;
+		self.uses.remove(used);
+		*used = now;
+		self.uses.insert(now, key.clone());
+		Some(value.clone())
+	}
+
+	fn insert(&mut self, key: K, value: V) {
+		let now = self.tick();
+		if let Some((_, used)) = self.entries.remove(&key) {
+			self.uses.remove(&used);
+		} else if self.entries.len() >= self.capacity {
+			let oldest = self.uses.keys().next().cloned();
+			if let Some(key) = oldest.and_then(|used| self.uses.remove(&used)) {
+				self.entries.remove(&key);
+			}
+		}
+		self.entries.insert(key.clone(), (value, now));
+		self.uses.insert(now, key);
+	}
+}
+
+/// Valid results by block and extrinsic hash.
+type Cache<A> = Lru<(BlockHash<A>, <A as ChainApi>::Hash), TransactionValidity>;
+
+/// Wraps the chain API of the pool. Token extrinsics carry proofs that are
+/// costly to verify, an extrinsic gossiped or submitted again at the same
+/// block gets the result of its first validation instead. Only valid results
+/// are kept, the pool drops invalid extrinsics and bans their hash anyway.
+pub struct CachedChainApi<A: ChainApi> {
+	inner: A,
+	cache: Arc<Mutex<Cache<A>>>,
+}
+
+impl<A: ChainApi> CachedChainApi<A> {
+	/// Cache the validation results of `inner`.
+	pub fn new(inner: A) -> Self {
+		CachedChainApi { inner, cache: Arc::new(Mutex::new(Lru::new(CACHE_SIZE))) }
+	}
+}
+
+impl<A: ChainApi + 'static> ChainApi for CachedChainApi<A> where
+	A::ValidationFuture: 'static,
+	A::Error: Send + 'static,
+{
+	type Block = A::Block;
+	type Hash = A::Hash;
+	type Error = A::Error;
+	type ValidationFuture = Pin<Box<dyn Future<Output = Result<TransactionValidity, A::Error>> + Send>>;
+	type BodyFuture = A::BodyFuture;
+
+	fn validate_transaction(
+		&self,
+		at: &BlockId<Self::Block>,
+		uxt: ExtrinsicFor<Self>,
+	) -> Self::ValidationFuture {
+		let block = match self.inner.block_id_to_hash(at) {
+			Ok(Some(block)) => block,
+			_ => return Box::pin(self.inner.validate_transaction(at, uxt)),
+		};
+		let key = (block, self.inner.hash_and_length(&uxt).0);
+		if let Some(validity) = self.cache.lock().ok().and_then(|mut cache| cache.get(&key)) {
+			return Box::pin(future::ready(Ok(validity)));
+		}
+
+		let cache = self.cache.clone();
+		Box::pin(self.inner.validate_transaction(at, uxt).map(move |result| {
+			if let Ok(validity @ Ok(_)) = &result {
+				if let Ok(mut cache) = cache.lock() {
+					cache.insert(key, validity.clone());
+				}
+			}
+			result
+		}))
+	}
+
+	fn block_id_to_number(
+		&self,
+		at: &BlockId<Self::Block>,
+	) -> Result<Option<NumberFor<Self>>, Self::Error> {
+		self.inner.block_id_to_number(at)
+	}
+
+	fn block_id_to_hash(
+		&self,
+		at: &BlockId<Self::Block>,
+	) -> Result<Option<BlockHash<Self>>, Self::Error> {
+		self.inner.block_id_to_hash(at)
+	}
+
+	fn hash_and_length(&self, uxt: &ExtrinsicFor<Self>) -> (Self::Hash, usize) {
+		self.inner.hash_and_length(uxt)
+	}
+
+	fn block_body(&self, at: &BlockId<Self::Block>) -> Self::BodyFuture {
+		self.inner.block_body(at)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use codec::Encode;
+	use sc_transaction_pool::txpool::error;
+	use sp_core::{H256, blake2_256};
+	use sp_runtime::{
+		testing::{Block as RawBlock, ExtrinsicWrapper},
+		transaction_validity::ValidTransaction,
+	};
+
+	type Extrinsic = ExtrinsicWrapper<u64>;
+	type Block = RawBlock<Extrinsic>;
+
+	/// Finds everything valid at the genesis hash and counts its calls.
+	#[derive(Default)]
+	struct CountingApi {
+		calls: Arc<AtomicUsize>,
+	}
+
+	impl ChainApi for CountingApi {
+		type Block = Block;
+		type Hash = H256;
+		type Error = error::Error;
+		type ValidationFuture = future::Ready<error::Result<TransactionValidity>>;
+		type BodyFuture = future::Ready<error::Result<Option<Vec<Extrinsic>>>>;
+
+		fn validate_transaction(&self, _: &BlockId<Block>, _: Extrinsic) -> Self::ValidationFuture {
+			self.calls.fetch_add(1, Ordering::SeqCst);
+			future::ready(Ok(Ok(ValidTransaction::default())))
+		}
+
+		fn block_id_to_number(&self, _: &BlockId<Block>) -> error::Result<Option<u64>> {
+			Ok(Some(0))
+		}
+
+		fn block_id_to_hash(&self, _: &BlockId<Block>) -> error::Result<Option<H256>> {
+			Ok(Some(H256::default()))
+		}
+
+		fn hash_and_length(&self, uxt: &Extrinsic) -> (H256, usize) {
+			let encoded = uxt.encode();
+			(blake2_256(&encoded).into(), encoded.len())
+		}
+
+		fn block_body(&self, _: &BlockId<Block>) -> Self::BodyFuture {
+			future::ready(Ok(None))
+		}
+	}
+
+	#[test]
+	fn validates_once_per_block() {
+		let inner = CountingApi::default();
+		let calls = inner.calls.clone();
+		let api = CachedChainApi::new(inner);
+		let at = BlockId::Number(0);
+		for _ in 0..2 {
+			let validity = futures::executor::block_on(api.validate_transaction(&at, Extrinsic::from(1)));
+			assert!(validity.unwrap().is_ok());
+		}
+		assert_eq!(calls.load(Ordering::SeqCst), 1);
+	}
+
+	#[test]
+	fn drops_least_recently_used() {
+		let mut lru = Lru::new(2);
+		lru.insert(1, 'a');
+		lru.insert(2, 'b');
+		assert_eq!(lru.get(&1), Some('a'));
+		lru.insert(3, 'c');
+		assert_eq!(lru.get(&2), None);
+		assert_eq!(lru.get(&1), Some('a'));
+		assert_eq!(lru.get(&3), Some('c'));
+	}
+}
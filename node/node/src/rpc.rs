@@ -0,0 +1,426 @@
+//! JSON-RPC methods of the confidential token, served from its runtime API.
+//!
+//! Group elements and ciphers come as 0x prefixed hex strings, they do not
+//! fit the doubles JSON readers commonly parse numbers into.
+//!
+//! Wallets subscribe to the credits of the blocks as they are imported, or
+//! once GRANDPA finalized them. A credit of an imported block may vanish
+//! with a reorg, one of a finalized block stays.
+//!
+//! The runtime carries several tokens. Each method takes the name of the
+//! token module it is about, e.g. `SecondToken`, and defaults to `Token`.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use codec::Codec;
+use futures::{future, compat::{Compat, Future01CompatExt}, executor::ThreadPool, FutureExt, Stream, StreamExt};
+use futures01::{Future as Future01, Sink as Sink01};
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use jsonrpc_pubsub::{typed::Subscriber, SubscriptionId};
+use log::warn;
+use node_template_runtime::{opaque::Block, AccountId, BlockNumber};
+use sc_client_api::BlockchainEvents;
+use sc_rpc::Subscriptions;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+use sp_runtime::{generic::BlockId, traits::{Block as BlockT, Header as HeaderT}};
+use token_runtime_api::{
+	TokenApi as TokenRuntimeApi, AccountBalance, CipherParams, PendingState, Incoming, Statement, ProofFailure,
+	BigInt,
+};
+
+/// A credit to an account's pending cipher, as subscribers are notified of it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomingTransfer<Hash, AccountId> {
+	/// The block whose events credited it.
+	pub block: Hash,
+	/// The receiver, its key, the credit and the extrinsic.
+	#[serde(flatten)]
+	pub incoming: Incoming<AccountId>,
+}
+
+/// Which credits a subscriber is notified of, every credit if both are null.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomingFilter<AccountId> {
+	/// The token the credits are of, `Token` if null.
+	pub token: Option<String>,
+	/// Credits to this account.
+	pub who: Option<AccountId>,
+	/// Credits encrypted under this public key.
+	pub pubkey: Option<BigInt>,
+}
+
+/// Outcome of a dry run of the proofs of a call.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase", tag = "result")]
+pub enum Verification {
+	/// Every proof of the call verifies against the state.
+	Valid,
+	/// The bytes are not a call of the token.
+	BadCall,
+	/// The proofs are bound to a nonce the account already used.
+	#[serde(rename_all = "camelCase")]
+	StaleNonce {
+		/// The account's current nonce.
+		expected: u64,
+		/// The nonce the proofs are bound to.
+		found: u64,
+	},
+	/// The proofs are bound to a nonce the account has not reached yet.
+	#[serde(rename_all = "camelCase")]
+	FutureNonce {
+		/// The account's current nonce.
+		expected: u64,
+		/// The nonce the proofs are bound to.
+		found: u64,
+	},
+	/// A statement of the call does not hold.
+	#[serde(rename_all = "camelCase")]
+	Failed {
+		/// The proof, or `call` for checks of the call itself.
+		statement: &'static str,
+		/// The name of the pallet error.
+		error: String,
+	},
+}
+
+impl From<Result<(), ProofFailure>> for Verification {
+	fn from(result: Result<(), ProofFailure>) -> Self {
+		match result {
+			Ok(()) => Verification::Valid,
+			Err(ProofFailure::BadCall) => Verification::BadCall,
+			Err(ProofFailure::StaleNonce { expected, found }) => Verification::StaleNonce { expected, found },
+			Err(ProofFailure::FutureNonce { expected, found }) => Verification::FutureNonce { expected, found },
+			Err(ProofFailure::Failed { statement, error }) => Verification::Failed {
+				statement: match statement {
+					Statement::Call => "call",
+					Statement::Debit => "debitRange",
+					Statement::Remain => "remainRange",
+					Statement::Equality => "equality",
+					Statement::Ring => "ring",
+					Statement::Ownership => "ownership",
+				},
+				error: String::from_utf8_lossy(&error).into_owned(),
+			},
+		}
+	}
+}
+
+/// Token RPC methods, each reads the state of block `at`, the best block if
+/// none, of the token module named `token`, `Token` if none.
+#[rpc]
+pub trait TokenApi<BlockHash, AccountId, BlockNumber> {
+	/// RPC metadata
+	type Metadata;
+
+	/// The confidential balance of `who`, null without a confidential account.
+	#[rpc(name = "token_balance")]
+	fn balance(&self, who: AccountId, at: Option<BlockHash>, token: Option<String>) -> Result<Option<AccountBalance>>;
+
+	/// The cipher parameters and a scheduled migration.
+	#[rpc(name = "token_cipherParams")]
+	fn cipher_params(&self, at: Option<BlockHash>, token: Option<String>) -> Result<CipherParams<BlockNumber>>;
+
+	/// The width of range proofs in bits.
+	#[rpc(name = "token_rangeWidth")]
+	fn range_width(&self, at: Option<BlockHash>, token: Option<String>) -> Result<u32>;
+
+	/// Credits of `who` not yet rolled over, null without a confidential account.
+	#[rpc(name = "token_pending")]
+	fn pending(
+		&self,
+		who: AccountId,
+		at: Option<BlockHash>,
+		token: Option<String>,
+	) -> Result<Option<PendingState<BlockNumber>>>;
+
+	/// Checks the proofs of `call`, a SCALE encoded call of the token, as the next
+	/// call of `who`. Nothing is submitted.
+	#[rpc(name = "token_verifyProof")]
+	fn verify_proof(
+		&self,
+		who: AccountId,
+		call: Bytes,
+		at: Option<BlockHash>,
+		token: Option<String>,
+	) -> Result<Verification>;
+
+	/// Notifies of the credits matching `filter` of every new best block.
+	#[pubsub(subscription = "token_incoming", subscribe, name = "token_subscribeIncoming")]
+	fn subscribe_incoming(
+		&self,
+		metadata: Self::Metadata,
+		subscriber: Subscriber<IncomingTransfer<BlockHash, AccountId>>,
+		filter: IncomingFilter<AccountId>,
+	);
+
+	/// Ends a subscription to the credits of new best blocks.
+	#[pubsub(subscription = "token_incoming", unsubscribe, name = "token_unsubscribeIncoming")]
+	fn unsubscribe_incoming(&self, metadata: Option<Self::Metadata>, id: SubscriptionId) -> Result<bool>;
+
+	/// Notifies of the credits matching `filter` of every finalized block.
+	#[pubsub(subscription = "token_finalizedIncoming", subscribe, name = "token_subscribeFinalizedIncoming")]
+	fn subscribe_finalized_incoming(
+		&self,
+		metadata: Self::Metadata,
+		subscriber: Subscriber<IncomingTransfer<BlockHash, AccountId>>,
+		filter: IncomingFilter<AccountId>,
+	);
+
+	/// Ends a subscription to the credits of finalized blocks.
+	#[pubsub(subscription = "token_finalizedIncoming", unsubscribe, name = "token_unsubscribeFinalizedIncoming")]
+	fn unsubscribe_finalized_incoming(&self, metadata: Option<Self::Metadata>, id: SubscriptionId) -> Result<bool>;
+}
+
+/// Serves `TokenApi` from the runtime of `client`.
+pub struct Token<C> {
+	client: Arc<C>,
+	subscriptions: Subscriptions,
+}
+
+impl<C> Token<C> {
+	/// Token RPC over `client`, notifications are sent from `subscriptions`.
+	pub fn new(client: Arc<C>, subscriptions: Subscriptions) -> Self {
+		Token { client, subscriptions }
+	}
+}
+
+/// Runs the tasks sending notifications to subscribers.
+struct TaskExecutor(ThreadPool);
+
+type Task = Box<dyn Future01<Item = (), Error = ()> + Send>;
+
+impl futures01::future::Executor<Task> for TaskExecutor {
+	fn execute(&self, task: Task) -> std::result::Result<(), futures01::future::ExecuteError<Task>> {
+		self.0.spawn_ok(task.compat().map(drop));
+		Ok(())
+	}
+}
+
+/// Hashes of the blocks finalized along with `header`, oldest first. GRANDPA
+/// may finalize several blocks at once and notify of the last one only.
+fn finalized_since<C: HeaderBackend<Block>>(
+	client: &C,
+	last: &mut Option<BlockNumber>,
+	header: <Block as BlockT>::Header,
+) -> Vec<<Block as BlockT>::Hash> {
+	let number = *header.number();
+	let stop = last.unwrap_or_else(|| number.saturating_sub(1));
+	let mut hashes = Vec::new();
+	let mut next = Some(header);
+	while let Some(header) = next.take() {
+		if *header.number() <= stop {
+			break;
+		}
+		hashes.push(header.hash());
+		next = client.header(BlockId::hash(*header.parent_hash())).ok().flatten();
+	}
+	*last = Some(number);
+	hashes.reverse();
+	hashes
+}
+
+impl<C> Token<C> where
+	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+{
+	/// Sends `subscriber` the credits matching `filter` of each block of `blocks`,
+	/// rejects it if the runtime has no such token. Once the credits of a block
+	/// can not be read, the subscriber gets the error and no more notifications,
+	/// rather than missing the credits of that block unawares.
+	fn notify<A, N>(
+		&self,
+		subscriber: Subscriber<IncomingTransfer<<Block as BlockT>::Hash, A>>,
+		filter: IncomingFilter<A>,
+		blocks: Pin<Box<dyn Stream<Item = <Block as BlockT>::Hash> + Send>>,
+	) where
+		C::Api: TokenRuntimeApi<Block, A, N>,
+		A: Codec + Clone + Serialize + Send + Sync + 'static,
+		N: Codec,
+	{
+		let token = token_name(filter.token);
+		match self.client.runtime_api().range_width(&BlockId::hash(self.client.info().best_hash), token.clone()) {
+			Ok(Some(_)) => (),
+			Ok(None) => {
+				let _ = subscriber.reject(unknown_token(&token));
+				return;
+			},
+			Err(e) => {
+				let _ = subscriber.reject(runtime_error(e));
+				return;
+			},
+		}
+		let (who, pubkey) = (filter.who, filter.pubkey);
+		let client = self.client.clone();
+		/* None after an error, the stream ends there */
+		let transfers = blocks
+			.map(move |block| {
+				let notifications = match client.runtime_api()
+					.incoming(&BlockId::hash(block), token.clone(), who.clone(), pubkey.clone())
+				{
+					Ok(Some(incoming)) => incoming.into_iter()
+						.map(|incoming| Some(Ok(IncomingTransfer { block, incoming })))
+						.collect::<Vec<_>>(),
+					Ok(None) => vec![Some(Err(unknown_token(&token))), None],
+					Err(e) => vec![Some(Err(runtime_error(e))), None],
+				};
+				futures::stream::iter(notifications)
+			})
+			.flatten()
+			.take_while(|notification| future::ready(notification.is_some()))
+			.filter_map(future::ready)
+			.map(Ok::<_, ()>);
+
+		self.subscriptions.add(subscriber, |sink| {
+			sink
+				.sink_map_err(|e| warn!("Error sending token notifications: {:?}", e))
+				.send_all(Compat::new(transfers))
+				.map(|_| ())
+		});
+	}
+}
+
+impl<C: HeaderBackend<Block>> Token<C> {
+	fn block(&self, at: Option<<Block as BlockT>::Hash>) -> BlockId<Block> {
+		BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash))
+	}
+}
+
+/// The runtime API call failed, e.g. the block is unknown.
+const RUNTIME_ERROR: i64 = 1;
+
+fn runtime_error<E: std::fmt::Debug>(e: E) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(RUNTIME_ERROR),
+		message: "Unable to query the token state.".into(),
+		data: Some(format!("{:?}", e).into()),
+	}
+}
+
+/// The runtime has no token module of the name given.
+const UNKNOWN_TOKEN: i64 = 2;
+
+fn unknown_token(token: &[u8]) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(UNKNOWN_TOKEN),
+		message: "No such token.".into(),
+		data: Some(String::from_utf8_lossy(token).into_owned().into()),
+	}
+}
+
+/// The token a call without a token name is about.
+const DEFAULT_TOKEN: &str = "Token";
+
+fn token_name(token: Option<String>) -> Vec<u8> {
+	token.unwrap_or_else(|| DEFAULT_TOKEN.into()).into_bytes()
+}
+
+/// The outcome of a runtime API call about `token`, None if there is no such token.
+fn routed<T, E: std::fmt::Debug>(token: &[u8], result: std::result::Result<Option<T>, E>) -> Result<T> {
+	result.map_err(runtime_error)?.ok_or_else(|| unknown_token(token))
+}
+
+impl<C, A, N> TokenApi<<Block as BlockT>::Hash, A, N> for Token<C> where
+	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + BlockchainEvents<Block> + Send + Sync + 'static,
+	C::Api: TokenRuntimeApi<Block, A, N>,
+	A: Codec + Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+	N: Codec,
+{
+	type Metadata = sc_rpc::Metadata;
+
+	fn balance(
+		&self,
+		who: A,
+		at: Option<<Block as BlockT>::Hash>,
+		token: Option<String>,
+	) -> Result<Option<AccountBalance>> {
+		let token = token_name(token);
+		routed(&token, self.client.runtime_api().balance(&self.block(at), token.clone(), who))
+	}
+
+	fn cipher_params(&self, at: Option<<Block as BlockT>::Hash>, token: Option<String>) -> Result<CipherParams<N>> {
+		let token = token_name(token);
+		routed(&token, self.client.runtime_api().cipher_params(&self.block(at), token.clone()))
+	}
+
+	fn range_width(&self, at: Option<<Block as BlockT>::Hash>, token: Option<String>) -> Result<u32> {
+		let token = token_name(token);
+		routed(&token, self.client.runtime_api().range_width(&self.block(at), token.clone()))
+	}
+
+	fn pending(
+		&self,
+		who: A,
+		at: Option<<Block as BlockT>::Hash>,
+		token: Option<String>,
+	) -> Result<Option<PendingState<N>>> {
+		let token = token_name(token);
+		routed(&token, self.client.runtime_api().pending(&self.block(at), token.clone(), who))
+	}
+
+	fn verify_proof(
+		&self,
+		who: A,
+		call: Bytes,
+		at: Option<<Block as BlockT>::Hash>,
+		token: Option<String>,
+	) -> Result<Verification> {
+		let token = token_name(token);
+		routed(&token, self.client.runtime_api().verify_call(&self.block(at), token.clone(), who, call.to_vec()))
+			.map(Verification::from)
+	}
+
+	fn subscribe_incoming(
+		&self,
+		_metadata: Self::Metadata,
+		subscriber: Subscriber<IncomingTransfer<<Block as BlockT>::Hash, A>>,
+		filter: IncomingFilter<A>,
+	) {
+		let blocks = self.client.import_notification_stream()
+			.filter(|notification| future::ready(notification.is_new_best))
+			.map(|notification| notification.hash);
+		self.notify(subscriber, filter, Box::pin(blocks));
+	}
+
+	fn unsubscribe_incoming(&self, _metadata: Option<Self::Metadata>, id: SubscriptionId) -> Result<bool> {
+		Ok(self.subscriptions.cancel(id))
+	}
+
+	fn subscribe_finalized_incoming(
+		&self,
+		_metadata: Self::Metadata,
+		subscriber: Subscriber<IncomingTransfer<<Block as BlockT>::Hash, A>>,
+		filter: IncomingFilter<A>,
+	) {
+		let client = self.client.clone();
+		let mut last = None;
+		let blocks = self.client.finality_notification_stream()
+			.map(move |notification| futures::stream::iter(finalized_since(&*client, &mut last, notification.header)))
+			.flatten();
+		self.notify(subscriber, filter, Box::pin(blocks));
+	}
+
+	fn unsubscribe_finalized_incoming(&self, _metadata: Option<Self::Metadata>, id: SubscriptionId) -> Result<bool> {
+		Ok(self.subscriptions.cancel(id))
+	}
+}
+
+/// The RPC extensions of a full node.
+pub fn create_full<C>(client: Arc<C>) -> std::result::Result<jsonrpc_core::IoHandler<sc_rpc::Metadata>, String> where
+	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + BlockchainEvents<Block> + Send + Sync + 'static,
+	C::Api: TokenRuntimeApi<Block, AccountId, BlockNumber>,
+{
+	let pool = ThreadPool::builder()
+		.pool_size(1)
+		.name_prefix("token-rpc-")
+		.create()
+		.map_err(|e| format!("Unable to start the token RPC notifications: {:?}", e))?;
+	let subscriptions = Subscriptions::new(Arc::new(TaskExecutor(pool)));
+	let mut io = jsonrpc_core::IoHandler::default();
+	io.extend_with(TokenApi::<_, AccountId, BlockNumber>::to_delegate(Token::new(client, subscriptions)));
+	Ok(io)
+}
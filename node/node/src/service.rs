@@ -36,7 +36,9 @@ macro_rules! new_full_start {
 				Ok(sc_client::LongestChain::new(backend.clone()))
 			})?
 			.with_transaction_pool(|config, client, _fetcher| {
-				let pool_api = sc_transaction_pool::FullChainApi::new(client.clone());
+				let pool_api = crate::pool::CachedChainApi::new(
+					sc_transaction_pool::FullChainApi::new(client.clone())
+				);
 				Ok(sc_transaction_pool::BasicPool::new(config, std::sync::Arc::new(pool_api)))
 			})?
 			.with_import_queue(|_config, client, mut select_chain, _transaction_pool| {
@@ -94,6 +96,9 @@ pub fn new_full(config: Configuration)
 			let provider = client as Arc<dyn StorageAndProofProvider<_, _>>;
 			Ok(Arc::new(GrandpaFinalityProofProvider::new(backend, provider)) as _)
 		})?
+		.with_rpc_extensions(|builder| -> Result<_, ServiceError> {
+			crate::rpc::create_full(builder.client().clone()).map_err(Into::into)
+		})?
 		.build()?;
 
 	if participates_in_consensus {
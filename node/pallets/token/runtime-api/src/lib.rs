@@ -0,0 +1,44 @@
+//! Runtime API of the confidential token pallet.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::Codec;
+use sp_std::vec::Vec;
+pub use token::{Disclosure, AccountBalance, CipherParams, PendingState, Incoming, Statement, ProofFailure, BigInt};
+
+//
+// A runtime may carry several token instances. Every call names the one
+// it is about by its module name in the runtime, e.g. b"Token", and
+// returns None when the runtime has no such token.
+//
+
+sp_api::decl_runtime_apis! {
+    /**
+     * Lets an auditor check what an account discloses about its balance
+     * against the state of the block the API is called at.
+     */
+    pub trait TokenAuditApi<AccountId> where AccountId: Codec {
+        /* The context the disclosure proofs of who answering challenge are bound to */
+        fn disclosure_context(token: Vec<u8>, who: AccountId, challenge: Vec<u8>) -> Option<Vec<u8>>;
+        /* false if who has no confidential account or the proof does not verify */
+        fn verify_disclosure(token: Vec<u8>, who: AccountId, challenge: Vec<u8>, disclosure: Disclosure<u128>) -> Option<bool>;
+    }
+
+    /**
+     * The confidential state at the block the API is called at, so that
+     * clients need not build storage keys themselves.
+     */
+    pub trait TokenApi<AccountId, BlockNumber> where AccountId: Codec, BlockNumber: Codec {
+        /* Some(None) if who has no confidential account */
+        fn balance(token: Vec<u8>, who: AccountId) -> Option<Option<AccountBalance>>;
+        fn cipher_params(token: Vec<u8>) -> Option<CipherParams<BlockNumber>>;
+        /* The width of range proofs in bits */
+        fn range_width(token: Vec<u8>) -> Option<u32>;
+        /* Some(None) if who has no confidential account */
+        fn pending(token: Vec<u8>, who: AccountId) -> Option<Option<PendingState<BlockNumber>>>;
+        /* Dry run of the proofs of call, an encoded call of the token, as who's next call */
+        fn verify_call(token: Vec<u8>, who: AccountId, call: Vec<u8>) -> Option<Result<(), ProofFailure>>;
+        /* Credits the events of the block added to pending, to who or under pubkey if given */
+        fn incoming(token: Vec<u8>, who: Option<AccountId>, pubkey: Option<BigInt>) -> Option<Vec<Incoming<AccountId>>>;
+    }
+}
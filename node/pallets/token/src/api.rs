@@ -0,0 +1,242 @@
+use codec::{Encode, Decode};
+use sp_runtime::RuntimeDebug;
+#[cfg(feature = "std")]
+use serde::{Serialize, Deserialize};
+
+use frame_support::dispatch::Vec;
+
+use crate::*;
+
+//
+// The confidential state as the runtime API hands it to clients.
+//
+// Group elements and ciphers are BigInt, so the node's RPC writes them
+// as hex strings, nothing gets rounded by a JSON reader. Nothing here
+// writes to storage: an account whose balance is due to roll over or to
+// switch to migrated parameters is shown as stored, with what its next
+// operation will do to it.
+//
+// Wallets learn they were paid from the events of a block: the runtime
+// API lists the credits its events added to pending, with the key they
+// are encrypted under as it stands at the end of the block.
+//
+// A dry run checks the proofs of a call against the latest state, the
+// way the pool does, and tells which statement fails and why. Proofs
+// built for a call that pays a confidential fee are bound to the nonce
+// after the fee's, the dry run reports them as FutureNonce.
+//
+
+/* An account's balance entry */
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct AccountBalance {
+    pub pubkey: BigInt,
+    pub current: (BigInt, BigInt),
+    pub lock: (BigInt, BigInt),
+    /* The era of the parameters the balance is encrypted under */
+    pub era: u32,
+    /* The nonce the account's next proofs are bound to */
+    pub nonce: u64,
+}
+
+/* The cipher of an instance, zero before it is set up */
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct CipherParams<BlockNumber> {
+    pub generator: BigInt,
+    pub prime: BigInt,
+    pub second_generator: BigInt,
+    pub era: u32,
+    /* γ, p and h of a scheduled migration, and the block they apply from */
+    pub migration: Option<(BigInt, BigInt, BigInt, BlockNumber)>,
+}
+
+/* Credits an account received and has not rolled over yet */
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct PendingState<BlockNumber> {
+    pub pending: Option<(BigInt, BigInt)>,
+    /* The epoch the account last rolled over in */
+    pub last_roll_over: BlockNumber,
+    /* Whether the account's next operation rolls pending into current */
+    pub due: bool,
+}
+
+/* A credit added to an account's pending */
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct Incoming<AccountId> {
+    pub who: AccountId,
+    pub pubkey: BigInt,
+    pub credit: (BigInt, BigInt),
+    /* The index of the extrinsic in the block, None for on_finalize */
+    pub extrinsic: Option<u32>,
+}
+
+/* What a check of a call is about */
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum Statement {
+    /* Accounts, ring and amounts of the call, no proof involved */
+    Call,
+    /* The range proof of the amount debited */
+    Debit,
+    /* The range proof of what remains of current */
+    Remain,
+    /* The equality of the amounts debited or released and credited */
+    Equality,
+    /* The one-out-of-many proof of an anonymous transfer */
+    Ring,
+    /* The proof of the secret key authorising an unsigned transfer */
+    Ownership,
+}
+
+/* Why the proofs of a call do not verify against the latest state */
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum ProofFailure {
+    /* Not a call of this token */
+    BadCall,
+    /* Bound to a nonce the account already used, the state moved on */
+    StaleNonce { expected: u64, found: u64 },
+    /* Bound to a nonce the account has not reached yet */
+    FutureNonce { expected: u64, found: u64 },
+    /* statement does not hold, error is the name of the pallet error */
+    Failed { statement: Statement, error: Vec<u8> },
+}
+
+fn failed<T: Trait<I>, I: Instance>(statement: Statement, error: Error<T,I>) -> ProofFailure {
+    let name: &'static str = error.into();
+    ProofFailure::Failed { statement: statement, error: name.as_bytes().to_vec() }
+}
+
+fn pair((a, b): (u128, u128)) -> (BigInt, BigInt) {
+    (a.into(), b.into())
+}
+
+impl<T: Trait<I>, I: Instance> Module<T,I> {
+    /* who's balance entry, None without a confidential account */
+    pub fn account_balance(who: &T::AccountId) -> Option<AccountBalance> {
+        if !<BalanceMap<T,I>>::contains_key(who) {
+            return None;
+        }
+        let balance = <BalanceMap<T,I>>::get(who);
+        Some(AccountBalance {
+            pubkey: balance.pubkey.into(),
+            current: pair(balance.current),
+            lock: pair(balance.lock),
+            era: <AccountEra<T,I>>::get(who),
+            nonce: <ConfidentialNonce<T,I>>::get(who),
+        })
+    }
+
+    pub fn cipher_params() -> CipherParams<T::BlockNumber> {
+        let CipherInfo(gamma, prime) = Cipher::<I>::get();
+        CipherParams {
+            generator: gamma.into(),
+            prime: prime.into(),
+            second_generator: SecondGenerator::<I>::get().into(),
+            era: CipherEra::<I>::get(),
+            migration: <CipherMigration<T,I>>::get().map(|(CipherInfo(gamma, prime), h, at)| {
+                (gamma.into(), prime.into(), h.into(), at)
+            }),
+        }
+    }
+
+    /* The width range proofs must have */
+    pub fn proof_width() -> u32 {
+        Self::range_width() as u32
+    }
+
+    /* None without a confidential account */
+    pub fn pending_state(who: &T::AccountId) -> Option<PendingState<T::BlockNumber>> {
+        if !<BalanceMap<T,I>>::contains_key(who) {
+            return None;
+        }
+        let pending = <PendingMap<T,I>>::get(who);
+        let last_roll_over = <LastRollOver<T,I>>::get(who);
+        let due = pending.is_some()
+            && <AccountEra<T,I>>::get(who) == CipherEra::<I>::get()
+            && last_roll_over < Self::current_epoch();
+        Some(PendingState {
+            pending: pending.map(pair),
+            last_roll_over: last_roll_over,
+            due: due,
+        })
+    }
+
+    /**
+     * Check the proofs of call as who's next call, without writing
+     * anything. Calls without proofs pass.
+     */
+    pub fn verify_call(who: &T::AccountId, call: &Call<T,I>) -> Result<(), ProofFailure> {
+        let envelope = match Self::call_envelope(call) {
+            Some(envelope) => envelope,
+            None => match call {
+                Call::unsigned_transfer(.., authorisation) => authorisation,
+                _ => return Ok(()),
+            },
+        };
+        let cipher = Self::cipher().map_err(|e| failed(Statement::Call, e))?;
+        let expected = Self::confidential_nonce(who);
+        let (_, found, _) = envelope.unseal(&Self::params_hash(&cipher))
+            .map_err(|e| failed(Statement::Call, e.into()))?;
+        if found < expected {
+            return Err(ProofFailure::StaleNonce { expected: expected, found: found });
+        }
+        if found > expected {
+            return Err(ProofFailure::FutureNonce { expected: expected, found: found });
+        }
+        let balance = Self::balance_preview(&cipher, who);
+        Self::check_call(&cipher, who, expected, balance, call).map_err(|(statement, e)| failed(statement, e))
+    }
+
+    /**
+     * The credits event adds to pending, with the account they go to. A
+     * member of a ring is credited its credit minus its debit, what the
+     * sender and the decoys receive is indistinguishable.
+     */
+    pub fn credits(event: &Event<T,I>) -> Vec<(T::AccountId, (u128,u128))> {
+        match event {
+            RawEvent::Transfer(_, dest, _, credit) => vec![(dest.clone(), *credit)],
+            RawEvent::AnonymousTransfer(members, debits, credits) => {
+                let cipher = Cipher::<I>::get().to_cipher();
+                members.iter().cloned()
+                    .zip(debits.iter().zip(credits.iter()).map(|(debit, credit)| cipher.minus(*credit, *debit)))
+                    .collect()
+            },
+            RawEvent::Minted(who, _, credit) => vec![(who.clone(), *credit)],
+            RawEvent::Shielded(who, _, credit) => vec![(who.clone(), *credit)],
+            _ => Vec::new(),
+        }
+    }
+
+    /**
+     * The credits of events, each with the index of the extrinsic that
+     * emitted it, going to who or encrypted under key when they are given.
+     */
+    pub fn incoming<E: IntoIterator<Item = (Option<u32>, Event<T,I>)>>(
+        events: E,
+        who: Option<&T::AccountId>,
+        key: Option<u128>,
+    ) -> Vec<Incoming<T::AccountId>> {
+        let mut incoming = Vec::new();
+        for (extrinsic, event) in events {
+            for (receiver, credit) in Self::credits(&event) {
+                let pubkey = <BalanceMap<T,I>>::get(&receiver).pubkey;
+                if who.map_or(false, |who| *who != receiver) || key.map_or(false, |key| key != pubkey) {
+                    continue;
+                }
+                incoming.push(Incoming {
+                    who: receiver,
+                    pubkey: pubkey.into(),
+                    credit: pair(credit),
+                    extrinsic: extrinsic,
+                });
+            }
+        }
+        incoming
+    }
+}
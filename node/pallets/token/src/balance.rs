@@ -24,6 +24,10 @@ pub trait CipherBalance<B>
     fn set(self, cipher:&EGICipher<B>, b:B) -> Self;
     fn lock(self, cipher:&EGICipher<B>, b:B) -> Self;
     fn release_locked(self, cipher:&EGICipher<B>, b:B) -> Result<Self, &'static str>;
+    fn lock_encrypted(self, cipher:&EGICipher<B>, delta:(B,B)) -> Self;
+    fn release_encrypted(self, cipher:&EGICipher<B>) -> Result<(Self, (B,B)), &'static str>;
+    fn increase_encrypted(self, cipher:&EGICipher<B>, delta:(B,B)) -> Self;
+    fn decrease_encrypted(self, cipher:&EGICipher<B>, delta:(B,B)) -> Self;
     fn switch(self, cipher:&EGICipher<B>, npk:u128) -> Self;
     fn increase(self, cipher:&EGICipher<B>, delta:B) -> Self;
     fn decrease(self, cipher:&EGICipher<B>, delta:B) -> Self;
@@ -32,11 +36,17 @@ pub trait CipherBalance<B>
 #[derive(Encode, Decode, Default, Clone, PartialEq)]
 pub struct CipherText<T>{
     pub pubkey: T,
+    /* Randomness of the amounts in clear that lock and release_locked
+     * encode. Every account keeps it at zero: genesis, set_pubkey,
+     * rotate_key and migrate_balance all start from rel = 0. */
     pub rel: T,
     pub current: (T, T),
     pub lock: (T, T),
 }
 
+/* (γ^0 * y^0, γ^0), the cipher of amount zero that adds nothing */
+pub const UNLOCKED:(u128,u128) = (1, 1);
+
 impl CipherBalance<u128> for CipherText<u128>{
 
     type Balance = u128;
@@ -46,13 +56,14 @@ impl CipherBalance<u128> for CipherText<u128>{
      * Set the lock to be the cipher of amount zero
      */
     fn make(cipher:&EGICipher<u128>, pk:u128, b:u128, r:u128) -> Self {
-        CipherText {pubkey:pk, rel:r, current:cipher.encode(pk, b, r), lock:cipher.encode(pk, 0, r)}
+        CipherText {pubkey:pk, rel:r, current:cipher.encode(pk, b, r), lock:UNLOCKED}
     }
 
 
     /**
      * Lock amount of balance from self and increase the current amount.
-     * If some amount is already locked then add the locked amount togeter
+     * If some amount is already locked then add the locked amount togeter,
+     * with rel at zero the sum is the cipher of the total locked.
      */
 
     fn lock(self, cipher:&EGICipher<u128>, b:u128) -> Self {
@@ -65,17 +76,17 @@ impl CipherBalance<u128> for CipherText<u128>{
     /**
      * Amount can be locked several times but needs to be released in total
      * so that we dont have to provide another proof to show the locked amount is larger
-     * then the released amount.
+     * then the released amount. Several locks only match the cipher of their total
+     * because rel is zero, with any other rel the randomness would add up as well.
      */
     fn release_locked(self, cipher:&EGICipher<u128>, amount:u128) -> Result<CipherText<u128>, &'static str> {
         let t = cipher.encode(self.pubkey, amount, self.rel);
-        let lock = cipher.encode(self.pubkey, 0, self.rel);
         if self.lock == t {
             let x = CipherText {
                 pubkey:self.pubkey,
                 rel:self.rel,
                 current:self.current,
-                lock:(0,0)
+                lock:UNLOCKED
             };
             Ok(x)
         } else {
@@ -83,6 +94,42 @@ impl CipherBalance<u128> for CipherText<u128>{
         }
     }
 
+    /**
+     * Same as lock but the amount is already a ciphertext under our own key,
+     * so neither the lock nor the remaining balance reveals the amount.
+     */
+    fn lock_encrypted(self, cipher:&EGICipher<u128>, delta:(u128,u128)) -> Self {
+        let current = cipher.minus(self.current, delta);
+        let lock = cipher.plus(self.lock, delta);
+        CipherText {pubkey:self.pubkey, rel:self.rel, current:current, lock:lock}
+    }
+
+    /**
+     * Release the whole lock and hand back its ciphertext, the caller
+     * needs to prove where the locked amount goes.
+     */
+    fn release_encrypted(self, _cipher:&EGICipher<u128>) -> Result<(CipherText<u128>, (u128,u128)), &'static str> {
+        if self.lock == UNLOCKED {
+            Err(CipherBalanceException::ReleaseLockFailure.into())
+        } else {
+            let x = CipherText {
+                pubkey:self.pubkey,
+                rel:self.rel,
+                current:self.current,
+                lock:UNLOCKED
+            };
+            Ok((x, self.lock))
+        }
+    }
+
+    fn increase_encrypted(self, cipher:&EGICipher<u128>, delta:(u128,u128)) -> Self {
+        CipherText {pubkey:self.pubkey, rel:self.rel, current: cipher.plus(self.current, delta), lock:self.lock}
+    }
+
+    fn decrease_encrypted(self, cipher:&EGICipher<u128>, delta:(u128,u128)) -> Self {
+        CipherText {pubkey:self.pubkey, rel:self.rel, current: cipher.minus(self.current, delta), lock:self.lock}
+    }
+
     fn set(self, cipher:&EGICipher<u128>, b:u128) -> Self {
         let current = cipher.encode(self.pubkey, b, self.rel);
         CipherText {pubkey:self.pubkey, rel:self.rel, current:current, lock:self.lock}
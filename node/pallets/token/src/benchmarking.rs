@@ -0,0 +1,347 @@
+use super::*;
+
+use frame_benchmarking::{benchmarks, account};
+use sp_runtime::traits::{Bounded, OnFinalize};
+use system::RawOrigin;
+
+use crate::primering::PrimeRing;
+
+//
+// Benchmarks of the token extrinsics, for the default instance.
+//
+// Every proof is built with the prover against accounts whose secret
+// keys the benchmark picks, so each call takes its success path with all
+// of its checks. b is the width of range proofs, the cost of nearly every
+// extrinsic grows linearly with it. The proof constants of weights.rs
+// were fitted on native timings of the verifiers these benchmarks run,
+// running them through the runtime's Benchmark API refits them for Wasm
+// and storage. reset_balance only exists with the dev feature and is not
+// benchmarked.
+//
+
+const SEED: u32 = 0;
+
+/* The testing group, the largest safe prime below 2^31, its order (p - 1) / 2 allows ranges up to 28 bits */
+const PRIME: u128 = 2147483579;
+const GAMMA: u128 = 7;
+const H: u128 = 48271;
+const MAX_BITS: u32 = 28;
+
+/* Locks queued in one block, verified together in on_finalize */
+const MAX_LOCKS: u32 = 100;
+
+/* The largest safe primes below 2^16, 2^31, 2^61 and 2^80, squares such as 4 and 9 have order (p - 1) / 2 */
+const SAFE_PRIMES: [u128; 4] = [65267, 2147483579, 2305843009213691579, 1208925819614629174700339];
+
+/* The group migrate_balance moves to */
+const NEXT_PRIME: u128 = 2305843009213691579;
+const NEXT_GAMMA: u128 = 3;
+
+fn cipher() -> EGICipher<u128> {
+    EGICipher { gamma: GAMMA, prime: PRIME }
+}
+
+/* Park-Miller, predictable, which is fine as nothing here is secret */
+fn rng() -> impl FnMut() -> u128 {
+    let mut seed = 2020u128;
+    move || { seed = PRIME.mul(seed, 48271); seed }
+}
+
+/* The testing cipher with range proofs of bits */
+fn setup(bits: u32) {
+    Cipher::<DefaultInstance>::put(CipherInfo(GAMMA, PRIME));
+    SecondGenerator::<DefaultInstance>::put(H);
+    RangeWidth::<DefaultInstance>::put(bits);
+}
+
+/* The largest amount within a range of bits */
+fn max_value(bits: u32) -> u128 {
+    (1u128 << bits) - 1
+}
+
+/* An account holding value under a key of the benchmark, with that key's secret */
+fn funded<T: Trait, R: FnMut() -> u128>(name: &'static str, index: u32, value: u128, rng: &mut R) -> (T::AccountId, u128) {
+    let who: T::AccountId = account(name, index, SEED);
+    let secret = rng() % (PRIME - 1);
+    let pubkey = PRIME.power(GAMMA, secret);
+    <BalanceMap<T>>::insert(&who, CipherText::make(&cipher(), pubkey, value, 0));
+    (who, secret)
+}
+
+/* Every account of the benchmarks is fresh, its proofs are bound to nonce 0 */
+fn envelope<P: Encode>(scheme: u16, proof: &P) -> ProofEnvelope {
+    prover::seal(&cipher(), H, scheme, 0, proof)
+}
+
+fn context<T: Trait>(who: &T::AccountId) -> Vec<u8> {
+    Module::<T>::proof_context(who, 0)
+}
+
+/* who's proof of what remains of current, with the tag of secret for this epoch */
+fn tagged<T: Trait, R: FnMut() -> u128>(who: &T::AccountId, secret: u128, range: RangeProof<u128>, rng: &mut R) -> TaggedRange<u128> {
+    let base = Module::<T>::epoch_generator(&cipher());
+    prover::tag_remain(&cipher(), base, secret, range, &context::<T>(who), rng)
+}
+
+benchmarks! {
+    _ {
+        let b in 1 .. MAX_BITS => ();
+        let l in 1 .. MAX_LOCKS => ();
+        let s in 1 .. 4 => ();
+    }
+
+    transfer {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let amount = max_value(b);
+        let (src, _) = funded::<T, _>("src", 0, amount, &mut rng);
+        let locked = <BalanceMap<T>>::get(&src).lock(&cipher(), amount);
+        <BalanceMap<T>>::insert(&src, locked);
+        let (dest, _) = funded::<T, _>("dest", 0, 0, &mut rng);
+    }: _(RawOrigin::Signed(src), amount, T::Lookup::unlookup(dest))
+
+    lock_balance {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        let (who, secret) = funded::<T, _>("who", 0, value, &mut rng);
+        let balance = <BalanceMap<T>>::get(&who);
+        let proof = prover::lock_balance(
+            &cipher(), secret, &balance, value, value / 2, b as usize, &context::<T>(&who), &mut rng,
+        );
+        let proof = tagged::<T, _>(&who, secret, proof, &mut rng);
+    }: _(RawOrigin::Signed(who), value / 2, envelope(SCHEME_TAGGED_RANGE, &proof))
+
+    /* The batch check of l queued locks, the share lock_balance pays for */
+    finalize_locks {
+        let b in ...;
+        let l in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        for i in 0 .. l {
+            let (who, secret) = funded::<T, _>("who", i, value, &mut rng);
+            let balance = <BalanceMap<T>>::get(&who);
+            let proof = prover::lock_balance(
+                &cipher(), secret, &balance, value, value / 2, b as usize, &context::<T>(&who), &mut rng,
+            );
+            let proof = tagged::<T, _>(&who, secret, proof, &mut rng);
+            <LockQueue<T>>::mutate(|queue| queue.push((who, value / 2, 0, proof)));
+        }
+    }: {
+        Module::<T>::on_finalize(T::BlockNumber::zero());
+    }
+
+    lock_encrypted {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        let (who, secret) = funded::<T, _>("who", 0, value, &mut rng);
+        let balance = <BalanceMap<T>>::get(&who);
+        let debit = prover::debit(
+            &cipher(), secret, &balance, value, value / 2, b as usize, &context::<T>(&who), &mut rng,
+        );
+        let remain_proof = tagged::<T, _>(&who, secret, debit.remain_proof, &mut rng);
+    }: _(
+        RawOrigin::Signed(who),
+        debit.delta,
+        envelope(SCHEME_SIGMA_RANGE, &debit.proof),
+        envelope(SCHEME_TAGGED_RANGE, &remain_proof)
+    )
+
+    transfer_encrypted {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let c = cipher();
+        let amount = max_value(b);
+        let (src, _) = funded::<T, _>("src", 0, amount, &mut rng);
+        let (dest, _) = funded::<T, _>("dest", 0, 0, &mut rng);
+        let src_key = <BalanceMap<T>>::get(&src).pubkey;
+        let dest_key = <BalanceMap<T>>::get(&dest).pubkey;
+        let (lock_rand, credit_rand) = (rng() % (PRIME - 1), rng() % (PRIME - 1));
+        let locked = <BalanceMap<T>>::get(&src).lock_encrypted(&c, c.encode(src_key, amount, lock_rand));
+        <BalanceMap<T>>::insert(&src, locked);
+        let credit = c.encode(dest_key, amount, credit_rand);
+        let proof = prover::equality_proof(
+            &c, src_key, lock_rand, dest_key, credit_rand, amount, &context::<T>(&src), &mut rng,
+        );
+    }: _(RawOrigin::Signed(src), T::Lookup::unlookup(dest), credit, envelope(SCHEME_EQUALITY, &proof))
+
+    confidential_transfer {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        let (src, secret) = funded::<T, _>("src", 0, value, &mut rng);
+        let (dest, _) = funded::<T, _>("dest", 0, 0, &mut rng);
+        let balance = <BalanceMap<T>>::get(&src);
+        let dest_key = <BalanceMap<T>>::get(&dest).pubkey;
+        let (debit, credit, proof) = prover::confidential_transfer(
+            &cipher(), secret, &balance, value, dest_key, value / 2, b as usize, &context::<T>(&src), &mut rng,
+        );
+        let remain_proof = tagged::<T, _>(&src, secret, debit.remain_proof, &mut rng);
+    }: _(
+        RawOrigin::Signed(src),
+        T::Lookup::unlookup(dest),
+        debit.delta,
+        credit,
+        envelope(SCHEME_SIGMA_RANGE, &debit.proof),
+        envelope(SCHEME_TAGGED_RANGE, &remain_proof),
+        envelope(SCHEME_EQUALITY, &proof)
+    )
+
+    /* confidential_transfer authorised by a proof of the sender's key */
+    unsigned_transfer {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        let (src, secret) = funded::<T, _>("src", 0, value, &mut rng);
+        let (dest, _) = funded::<T, _>("dest", 0, 0, &mut rng);
+        let balance = <BalanceMap<T>>::get(&src);
+        let dest_key = <BalanceMap<T>>::get(&dest).pubkey;
+        let (debit, credit, proof) = prover::confidential_transfer(
+            &cipher(), secret, &balance, value, dest_key, value / 2, b as usize, &context::<T>(&src), &mut rng,
+        );
+        let range_proof = envelope(SCHEME_SIGMA_RANGE, &debit.proof);
+        let remain_proof = envelope(SCHEME_TAGGED_RANGE, &tagged::<T, _>(&src, secret, debit.remain_proof, &mut rng));
+        let eq_proof = envelope(SCHEME_EQUALITY, &proof);
+        let authorisation = Module::<T>::authorisation_context(
+            &src, 0, &dest, debit.delta, credit, &range_proof, &remain_proof, &eq_proof,
+        );
+        let authorisation = prover::authorise(&cipher(), secret, &authorisation, &mut rng);
+    }: _(
+        RawOrigin::None,
+        T::Lookup::unlookup(src),
+        T::Lookup::unlookup(dest),
+        debit.delta,
+        credit,
+        range_proof,
+        remain_proof,
+        eq_proof,
+        envelope(SCHEME_KEY_OWNERSHIP, &authorisation)
+    )
+
+    /* The ring has RingSize members, the sender first */
+    anonymous_transfer {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        let mut members = Vec::new();
+        let mut secrets = Vec::new();
+        for i in 0 .. T::RingSize::get() {
+            let (who, secret) = funded::<T, _>("member", i, value, &mut rng);
+            members.push(who);
+            secrets.push(secret);
+        }
+        let balances = members.iter().map(|who| <BalanceMap<T>>::get(who)).collect::<Vec<_>>();
+        let keys = balances.iter().map(|balance| balance.pubkey).collect::<Vec<_>>();
+        let currents = balances.iter().map(|balance| balance.current).collect::<Vec<_>>();
+        let relayer: T::AccountId = account("relayer", 0, SEED);
+        let base = Module::<T>::epoch_generator(&cipher());
+        let transfer = prover::anonymous_transfer(
+            &cipher(), H, base, &keys, &currents, secrets[0], 0, value, 1, value / 2, b as usize,
+            &context::<T>(&relayer), &mut rng,
+        );
+        let ring = members.into_iter().map(T::Lookup::unlookup).collect::<Vec<_>>();
+    }: _(RawOrigin::Signed(relayer), ring, envelope(SCHEME_RING, &transfer))
+
+    shield {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let (who, _) = funded::<T, _>("who", 0, 0, &mut rng);
+        T::Currency::make_free_balance_be(&who, BalanceOf::<T, DefaultInstance>::max_value() / 2u32.into());
+        let amount: BalanceOf<T, DefaultInstance> = max_value(b).saturated_into();
+    }: _(RawOrigin::Signed(who), amount)
+
+    unshield {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        let (who, secret) = funded::<T, _>("who", 0, value, &mut rng);
+        let balance = <BalanceMap<T>>::get(&who);
+        let amount: BalanceOf<T, DefaultInstance> = (value / 2).saturated_into();
+        <ShieldedSupply<T>>::put(amount);
+        let proof = prover::unshield(
+            &cipher(), secret, &balance, value, value / 2, b as usize, &context::<T>(&who), &mut rng,
+        );
+        let proof = tagged::<T, _>(&who, secret, proof, &mut rng);
+    }: _(RawOrigin::Signed(who), amount, envelope(SCHEME_TAGGED_RANGE, &proof))
+
+    mint {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let (who, _) = funded::<T, _>("who", 0, 0, &mut rng);
+    }: _(RawOrigin::Root, T::Lookup::unlookup(who), max_value(b))
+
+    burn {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        let (who, secret) = funded::<T, _>("who", 0, value, &mut rng);
+        ConfidentialIssuance::<DefaultInstance>::put(value);
+        let balance = <BalanceMap<T>>::get(&who);
+        let proof = prover::unshield(
+            &cipher(), secret, &balance, value, value / 2, b as usize, &context::<T>(&who), &mut rng,
+        );
+        let proof = tagged::<T, _>(&who, secret, proof, &mut rng);
+    }: _(RawOrigin::Root, T::Lookup::unlookup(who), value / 2, envelope(SCHEME_TAGGED_RANGE, &proof))
+
+    /* Opening an account under a key */
+    set_pubkey {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let who: T::AccountId = account("who", 0, SEED);
+        let key = PRIME.power(GAMMA, rng() % (PRIME - 1));
+    }: _(RawOrigin::Signed(who), key)
+
+    /* Rotating the key of an existing account with pending credits to merge */
+    rotate_key {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        let (who, secret) = funded::<T, _>("who", 0, value / 2, &mut rng);
+        let balance = <BalanceMap<T>>::get(&who);
+        let pending = cipher().encode(balance.pubkey, value - value / 2, rng() % (PRIME - 1));
+        <PendingMap<T>>::insert(&who, pending);
+        let old = cipher().plus(balance.current, pending);
+        let key = PRIME.power(GAMMA, rng() % (PRIME - 1));
+        let (current, proof) = prover::migrate(
+            &cipher(), secret, old, value, &cipher(), key, b as usize, &context::<T>(&who), &mut rng,
+        );
+    }: _(RawOrigin::Signed(who), key, current, envelope(SCHEME_MIGRATION, &proof))
+
+    /* Parameters over the s-th of SAFE_PRIMES, on a chain without a cipher */
+    set_cipher_params {
+        let s in ...;
+        RangeWidth::<DefaultInstance>::put(1);
+        let prime = SAFE_PRIMES[s as usize - 1];
+    }: _(RawOrigin::Root, 4, prime, 9, T::BlockNumber::zero())
+
+    migrate_balance {
+        let b in ...;
+        setup(b);
+        let mut rng = rng();
+        let value = max_value(b);
+        let (who, secret) = funded::<T, _>("who", 0, value, &mut rng);
+        let next = EGICipher { gamma: NEXT_GAMMA, prime: NEXT_PRIME };
+        let next_h = NEXT_PRIME.power(NEXT_GAMMA, rng());
+        <CipherMigration<T>>::put((CipherInfo(NEXT_GAMMA, NEXT_PRIME), next_h, T::BlockNumber::max_value()));
+        let new_key = NEXT_PRIME.power(NEXT_GAMMA, rng());
+        let old = <BalanceMap<T>>::get(&who).current;
+        let (current, proof) = prover::migrate(
+            &cipher(), secret, old, value, &next, new_key, b as usize, &context::<T>(&who), &mut rng,
+        );
+    }: _(RawOrigin::Signed(who), new_key, current, envelope(SCHEME_MIGRATION, &proof))
+}
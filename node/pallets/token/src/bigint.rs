@@ -0,0 +1,69 @@
+use codec::{Encode, Decode};
+use sp_runtime::RuntimeDebug;
+#[cfg(feature = "std")]
+use serde::{Serialize, Serializer, Deserialize, Deserializer, de};
+
+//
+// Group elements and amounts in JSON.
+//
+// JSON readers commonly parse numbers into doubles, anything above 2^53
+// silently loses its low digits. A BigInt is written as a 0x prefixed
+// hex string instead, and read from a hex string, a decimal string or a
+// number small enough to be exact.
+//
+
+#[derive(Encode, Decode, Default, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub struct BigInt(pub u128);
+
+impl From<u128> for BigInt {
+    fn from(n: u128) -> Self {
+        BigInt(n)
+    }
+}
+
+impl From<BigInt> for u128 {
+    fn from(n: BigInt) -> Self {
+        n.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl Serialize for BigInt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{:x}", self.0))
+    }
+}
+
+#[cfg(feature = "std")]
+struct BigIntVisitor;
+
+#[cfg(feature = "std")]
+impl<'de> de::Visitor<'de> for BigIntVisitor {
+    type Value = BigInt;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a 0x prefixed hex string, a decimal string or an integer")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<BigInt, E> {
+        let parsed = if s.starts_with("0x") {
+            u128::from_str_radix(&s[2..], 16)
+        } else {
+            s.parse::<u128>()
+        };
+        parsed.map(BigInt).map_err(|_| E::invalid_value(de::Unexpected::Str(s), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<BigInt, E> {
+        Ok(BigInt(n as u128))
+    }
+
+    /* Floats are rejected, they may have been rounded already */
+}
+
+#[cfg(feature = "std")]
+impl<'de> Deserialize<'de> for BigInt {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        deserializer.deserialize_any(BigIntVisitor)
+    }
+}
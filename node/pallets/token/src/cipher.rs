@@ -69,16 +69,21 @@ impl<T:PrimeRing<T>> CipherFunctor<T, T, (T,T)> for EGICipher<T>
         (p.mul(gamma_exp_amt, p_exp_r), gamma_exp_r)
     }
 
+    /*
+     * ElGamal is homomorphic component-wise:
+     * (γ^a * y^r, γ^r) * (γ^b * y^s, γ^s) = (γ^(a+b) * y^(r+s), γ^(r+s))
+     * so ciphertexts built with different r can be combined. An amount
+     * encoded with r = 0, as public amounts are, leaves the second
+     * component as it is.
+     */
     fn plus(&self, v1:(T, T), v2:(T,T)) -> (T,T) {
         let p = self.prime;
-        /* We need to check v1.1 == v2.1 ? */
-        (p.mul(v1.0, v2.0), v1.1)
+        (p.mul(v1.0, v2.0), p.mul(v1.1, v2.1))
     }
 
     fn minus(&self, v1:(T, T), v2:(T,T)) -> (T,T) {
         let p = self.prime;
-        /* We need to check v1.1 == v2.1 ? */
-        (p.div(v1.0, v2.0), v1.1)
+        (p.div(v1.0, v2.0), p.div(v1.1, v2.1))
     }
 
     fn switch(&self, old:T, new:T, t:(T,T)) -> (T, T) {
@@ -104,6 +109,36 @@ impl<T:PrimeRing<T>> CipherFunctor<T, T, (T,T)> for EGICipher<T>
     }
 }
 
+#[cfg(test)]
 mod tests {
-    //use super::*;
+    use super::*;
+
+    const CIPHER: EGICipher<u128> = EGICipher {gamma: 7, prime: 2147483579};
+    const SECRET: u128 = 1_000_003;
+
+    fn decrypts(t:(u128, u128), b:u128) -> bool {
+        let p = CIPHER.prime;
+        p.div(t.0, p.power(t.1, SECRET)) == p.power(CIPHER.gamma, b)
+    }
+
+    /* Pairs of any randomness combine into a pair of the sum or difference */
+    #[test]
+    fn pairs_of_any_randomness_combine() {
+        let pk = CIPHER.prime.power(CIPHER.gamma, SECRET);
+        let balance = CIPHER.encode(pk, 100, 42);
+        assert!(decrypts(balance, 100));
+        assert!(decrypts(CIPHER.plus(balance, CIPHER.encode(pk, 30, 99)), 130));
+        assert!(decrypts(CIPHER.minus(balance, CIPHER.encode(pk, 30, 42)), 70));
+    }
+
+    /* A public amount, encoded with r = 0, leaves the second component alone */
+    #[test]
+    fn public_amounts_keep_the_randomness() {
+        let p = CIPHER.prime;
+        let pk = p.power(CIPHER.gamma, SECRET);
+        let balance = CIPHER.encode(pk, 100, 42);
+        let public = CIPHER.encode(pk, 30, 0);
+        assert_eq!(CIPHER.plus(balance, public), (p.mul(balance.0, public.0), balance.1));
+        assert_eq!(CIPHER.minus(balance, public), (p.div(balance.0, public.0), balance.1));
+    }
 }
@@ -0,0 +1,245 @@
+use core::{fmt, marker::PhantomData};
+
+use codec::{Encode, Decode};
+use frame_support::{
+    traits::{Get, IsSubType},
+    weights::{DispatchInfo, Weight},
+};
+use sp_runtime::{
+    traits::{Convert, SignedExtension, SaturatedConversion, Saturating},
+    transaction_validity::{
+        TransactionValidity,
+        TransactionValidityError,
+        InvalidTransaction,
+        ValidTransaction,
+    },
+};
+
+use crate::*;
+
+//
+// Transaction fees paid from the confidential balance.
+//
+// The sender of a token call states a fee f in clear, the chain burns it
+// from current as (γ^f, 1) and the range proof shows what remains of
+// current is not negative. Like every proof spending from current, it
+// carries the epoch tag of the sender's key. The burnt fee comes out of
+// the minted supply first and out of the shielded one for the rest,
+// balances do not tell minted value from shielded. No public account is
+// involved, so the call does not link the sender's confidential
+// activity to a public balance.
+//
+// The fee is charged before the call, from the balance the call then
+// starts with. The fee proof is bound to the sender's confidential nonce
+// n and moves it on, so the proofs of the call itself are built against
+// current minus the fee and bound to n + 1.
+//
+
+impl<T: Trait<I>, I: Instance> Module<T,I> {
+    /* Least confidential fee of an extrinsic of len bytes and weight */
+    pub fn confidential_fee(len: usize, weight: Weight) -> u128 {
+        T::ConfidentialBaseFee::get()
+            .saturating_add(T::ConfidentialByteFee::get().saturating_mul(len as u128))
+            .saturating_add(T::WeightToConfidentialFee::convert(weight))
+    }
+
+    /**
+     * who's balance as its next extrinsic sees it, a migrated balance
+     * or a roll over included, without writing anything.
+     */
+    pub(crate) fn balance_preview(cipher: &EGICipher<u128>, who: &T::AccountId) -> Option<CipherText<u128>> {
+        let balance = Self::settled_balance(who)?;
+        if <AccountEra<T,I>>::get(who) != CipherEra::<I>::get()
+            || <LastRollOver<T,I>>::get(who) >= Self::current_epoch() {
+            return Some(balance);
+        }
+        match <PendingMap<T,I>>::get(who) {
+            Some(pending) => Some(balance.increase_encrypted(cipher, pending)),
+            None => Some(balance),
+        }
+    }
+
+    /* Credits of who that balance_preview leaves in pending */
+    pub(crate) fn unmerged_pending(who: &T::AccountId) -> Option<(u128,u128)> {
+        if <AccountEra<T,I>>::get(who) != CipherEra::<I>::get()
+            || <LastRollOver<T,I>>::get(who) < Self::current_epoch() {
+            return None;
+        }
+        <PendingMap<T,I>>::get(who)
+    }
+
+    /**
+     * Minted and shielded supply once fee is burnt, minted value goes
+     * first. None if both together do not cover fee.
+     */
+    fn burn_fee(fee: u128) -> Option<(u128, BalanceOf<T,I>)> {
+        let issuance = <ConfidentialIssuance<I>>::get();
+        let rest = fee.saturating_sub(issuance);
+        let shielded = <ShieldedSupply<T,I>>::get().saturated_into::<u128>().checked_sub(rest)?;
+        Some((issuance.saturating_sub(fee), shielded.saturated_into()))
+    }
+
+    /* The fee as a debit of balance, once the proof shows balance covers it */
+    fn check_fee(
+        cipher: &EGICipher<u128>,
+        who: &T::AccountId,
+        nonce: u64,
+        balance: &CipherText<u128>,
+        fee: u128,
+        proof: &ProofEnvelope,
+    ) -> Result<(u128,u128), Error<T,I>> {
+        let debit = cipher.encode(balance.pubkey, Self::check_amount(fee)?, 0);
+        Self::verify_remain(cipher, who, nonce, balance.pubkey, cipher.minus(balance.current, debit), proof)?;
+        Ok(debit)
+    }
+}
+
+/* Why a fee can not be charged, as the pool sees it */
+fn fee_error<T: Trait<I>, I: Instance>(error: Error<T,I>) -> TransactionValidityError {
+    match error {
+        Error::<T,I>::StaleNonce => InvalidTransaction::Stale.into(),
+        Error::<T,I>::FutureNonce => InvalidTransaction::Future.into(),
+        _ => InvalidTransaction::Payment.into(),
+    }
+}
+
+/**
+ * Signed extension charging the fee of a token call from the sender's
+ * confidential balance. It only accepts calls of its own token instance.
+ */
+#[derive(Encode, Decode)]
+pub struct ChargeConfidentialFee<T: Trait<I> + Send + Sync, I: Instance = DefaultInstance> {
+    fee: u128,
+    proof: ProofEnvelope,
+    _phantom: PhantomData<fn() -> (T, I)>,
+}
+
+impl<T: Trait<I> + Send + Sync, I: Instance> ChargeConfidentialFee<T,I> {
+    /* Pay fee, with the range proof of the sender's current minus fee */
+    pub fn from(fee: u128, proof: ProofEnvelope) -> Self {
+        ChargeConfidentialFee {fee:fee, proof:proof, _phantom:PhantomData}
+    }
+}
+
+impl<T: Trait<I> + Send + Sync, I: Instance> Clone for ChargeConfidentialFee<T,I> {
+    fn clone(&self) -> Self {
+        Self::from(self.fee, self.proof.clone())
+    }
+}
+
+impl<T: Trait<I> + Send + Sync, I: Instance> PartialEq for ChargeConfidentialFee<T,I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.fee == other.fee && self.proof == other.proof
+    }
+}
+
+impl<T: Trait<I> + Send + Sync, I: Instance> Eq for ChargeConfidentialFee<T,I> {}
+
+impl<T: Trait<I> + Send + Sync, I: Instance> fmt::Debug for ChargeConfidentialFee<T,I> {
+    #[cfg(feature = "std")]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ChargeConfidentialFee<{:?}>", self.fee)
+    }
+    #[cfg(not(feature = "std"))]
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl<T: Trait<I> + Send + Sync, I: Instance> SignedExtension for ChargeConfidentialFee<T,I>
+    where <T as system::Trait>::Call: IsSubType<Module<T,I>, T>
+{
+    const IDENTIFIER: &'static str = "ChargeConfidentialFee";
+    type AccountId = T::AccountId;
+    type Call = <T as system::Trait>::Call;
+    type AdditionalSigned = ();
+    type DispatchInfo = DispatchInfo;
+    type Pre = ();
+
+    fn additional_signed(&self) -> Result<(), TransactionValidityError> { Ok(()) }
+
+    /**
+     * Everything above the least fee counts as a tip. The call's own
+     * proofs are checked from the nonce and balance the fee leaves.
+     */
+    fn validate(
+        &self,
+        who: &Self::AccountId,
+        call: &Self::Call,
+        info: Self::DispatchInfo,
+        len: usize,
+    ) -> TransactionValidity {
+        let call = match call.is_sub_type() {
+            Some(call) => call,
+            None => return Err(InvalidTransaction::Call.into()),
+        };
+        if self.fee < Module::<T,I>::confidential_fee(len, info.weight) {
+            return Err(InvalidTransaction::Payment.into());
+        }
+        let cipher = Module::<T,I>::cipher().map_err(fee_error)?;
+        let nonce = Module::<T,I>::confidential_nonce(who);
+        let priority = self.fee.saturated_into();
+        /* paid after earlier calls of who, neither fee nor call can be checked yet */
+        let (_, bound, _) = self.proof.unseal(&Module::<T,I>::params_hash(&cipher))
+            .map_err(|_| InvalidTransaction::Payment)?;
+        if bound > nonce {
+            let mut provides = vec![Module::<T,I>::nonce_tag(who, bound)];
+            if Module::<T,I>::call_envelope(call).is_some() {
+                provides.push(Module::<T,I>::nonce_tag(who, bound.saturating_add(1)));
+            }
+            return Ok(ValidTransaction {
+                priority: priority,
+                requires: vec![Module::<T,I>::nonce_tag(who, bound - 1)],
+                provides: provides,
+                longevity: Module::<T,I>::proof_longevity(),
+                ..Default::default()
+            });
+        }
+        let balance = Module::<T,I>::balance_preview(&cipher, who)
+            .ok_or(InvalidTransaction::Payment)?;
+        let debit = Module::<T,I>::check_fee(&cipher, who, nonce, &balance, self.fee, &self.proof)
+            .map_err(fee_error)?;
+        let rest = balance.decrease_encrypted(&cipher, debit);
+        let paid = ValidTransaction {
+            priority: priority,
+            provides: vec![Module::<T,I>::nonce_tag(who, nonce)],
+            longevity: Module::<T,I>::proof_longevity(),
+            ..Default::default()
+        };
+        Ok(paid.combine_with(Module::<T,I>::validate_from(&cipher, who, nonce.saturating_add(1), Some(rest), call)?))
+    }
+
+    fn pre_dispatch(
+        self,
+        who: &Self::AccountId,
+        call: &Self::Call,
+        info: Self::DispatchInfo,
+        len: usize,
+    ) -> Result<(), TransactionValidityError> {
+        if call.is_sub_type().is_none() {
+            return Err(InvalidTransaction::Call.into());
+        }
+        if self.fee < Module::<T,I>::confidential_fee(len, info.weight) {
+            return Err(InvalidTransaction::Payment.into());
+        }
+        let cipher = Module::<T,I>::cipher().map_err(fee_error)?;
+        Module::<T,I>::ensure_account(who).map_err(fee_error)?;
+        let nonce = Module::<T,I>::next_nonce(who).map_err(fee_error)?;
+        let (issuance, shielded) = Module::<T,I>::burn_fee(self.fee)
+            .ok_or(InvalidTransaction::Payment)?;
+        let balance = Module::<T,I>::balance_preview(&cipher, who)
+            .ok_or(InvalidTransaction::Payment)?;
+        let debit = Module::<T,I>::check_fee(&cipher, who, Module::<T,I>::confidential_nonce(who), &balance, self.fee, &self.proof)
+            .map_err(fee_error)?;
+
+        // once we reach this spot, no chance to raise exception
+        Module::<T,I>::roll_over(&cipher, who);
+        <ConfidentialNonce<T,I>>::insert(who, nonce);
+        <ConfidentialIssuance<I>>::put(issuance);
+        <ShieldedSupply<T,I>>::put(shielded);
+        let who_new = balance.decrease_encrypted(&cipher, debit);
+        Module::<T,I>::deposit_event(RawEvent::FeePaid(who.clone(), self.fee, who_new.current));
+        <BalanceMap<T,I>>::insert(who, who_new);
+        Ok(())
+    }
+}
@@ -3,32 +3,93 @@ pub use primitive_types::{U256};
 pub mod balance;
 
 use sp_runtime::{
-    DispatchError,
     traits::{
         StaticLookup,
+        CheckedDiv,
+        CheckedAdd,
+        CheckedSub,
+        SaturatedConversion,
+        Saturating,
+        Zero,
+        One,
+        Convert,
     },
+    transaction_validity::TransactionPriority,
 };
 
 use system::{
     ensure_signed,
     ensure_root,
+    ensure_none,
 };
 
-use crate::cipher::{
-    EGICipher,
-    CipherFunctor,
-};
+pub use crate::cipher::EGICipher;
+use crate::cipher::CipherFunctor;
 use crate::proof::{
     CipherProof,
+    CipherEquality,
+};
+pub use crate::proof::{
+    EqualityProof,
+    RangeProof,
+    sigma::SigmaProof,
+    disclosure::Disclosure,
+    envelope::{
+        ProofEnvelope,
+        EnvelopeError,
+        GROUP_ELGAMAL_ZP,
+        GROUP_ELGAMAL_QR,
+        SCHEME_LEGACY_BITS,
+        SCHEME_SIGMA_RANGE,
+        SCHEME_EQUALITY,
+        SCHEME_RING,
+        SCHEME_MIGRATION,
+        SCHEME_KEY_OWNERSHIP,
+        SCHEME_TAGGED_RANGE,
+    },
+    tag::TaggedRange,
+};
+use crate::proof::sigma;
+use crate::proof::disclosure::CipherDisclosure;
+use crate::proof::migration::{
+    self,
+    MigrationProof,
+};
+use crate::proof::tag;
+use crate::primering;
+use crate::proof::envelope::{
+    self,
+    decode_body,
+};
+use crate::ring::RingProof;
+pub use crate::ring::{
+    AnonymousTransfer,
+    AnonymousProof,
+    BitCommitment,
+    MemberProof,
 };
 
 pub use crate::balance::{
     CipherText,
     CipherBalance,
 };
+use crate::balance::UNLOCKED;
+pub use crate::fee::ChargeConfidentialFee;
+pub use crate::bigint::BigInt;
+pub use crate::api::{
+    AccountBalance,
+    CipherParams,
+    PendingState,
+    Incoming,
+    Statement,
+    ProofFailure,
+};
 
 use frame_support::{
-    decl_storage, decl_module, decl_event, dispatch
+    decl_storage, decl_module, decl_event, decl_error, dispatch,
+    dispatch::Vec,
+    traits::{Get, Currency, ExistenceRequirement, WithdrawReason},
+    weights::{FunctionOf, DispatchClass, Weight},
 };
 
 use codec::{Encode, Decode};
@@ -36,6 +97,24 @@ use codec::{Encode, Decode};
 mod primering;
 mod cipher;
 mod proof;
+mod ring;
+mod bigint;
+mod weights;
+mod fee;
+mod validation;
+mod api;
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+/* Builds the proofs of the extrinsics, for clients, tests and benchmarks */
+#[cfg(any(feature = "std", feature = "runtime-benchmarks"))]
+pub mod prover;
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod tests;
+
+/* Public balance of the currency shield and unshield move value between */
+type BalanceOf<T, I> = <<T as Trait<I>>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 
 #[derive(Encode, Decode, Default, Clone, PartialEq)]
 struct CipherInfo(u128, u128);
@@ -49,16 +128,31 @@ impl CipherInfo {
 /// The module's configuration trait.
 pub trait Trait<I: Instance = DefaultInstance>: system::Trait {
     type Balance;
-    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
-}
-
-/*
-impl<T:Trait<I>, I: Instance> Trait<I> for T {
-    type Event = Event<T>;
-    type TransferHistory = TransferHistory;
-    type OnNewAccount = OnNewAccount<Self::AccountId>;
+    type Event: From<Event<Self, I>> + Into<<Self as system::Trait>::Event>;
+    /// Number of blocks in an epoch. Incoming transfers stay pending until
+    /// the receiver's first operation in a later epoch.
+    type EpochLength: Get<Self::BlockNumber>;
+    /// Number of accounts in the ring of an anonymous transfer,
+    /// needs to be a power of two.
+    type RingSize: Get<u32>;
+    /// Width of range proofs in bits, amounts are proved to be below
+    /// 2^RangeBits. Twice that must stay below the group order. Genesis
+    /// may set a narrower width, weights are computed with this one.
+    type RangeBits: Get<u32>;
+    /// The public currency value is shielded from and unshielded into.
+    type Currency: Currency<Self::AccountId>;
+    /// Confidential fee of every token call paid with ChargeConfidentialFee.
+    type ConfidentialBaseFee: Get<u128>;
+    /// Confidential fee per byte of such a call.
+    type ConfidentialByteFee: Get<u128>;
+    /// Confidential fee of the weight of such a call.
+    type WeightToConfidentialFee: Convert<Weight, u128>;
+    /// Pool priority of unsigned transfers, they pay no fee and should
+    /// only fill what paying calls leave of a block.
+    type UnsignedPriority: Get<TransactionPriority>;
+    /// Blocks an account waits between two unsigned transfers.
+    type UnsignedInterval: Get<Self::BlockNumber>;
 }
-*/
 
 /* We need implement Balance trait as follows
  * One
@@ -80,27 +174,709 @@ where
 */
 
 impl<T:Trait<I>, I: Instance> Module<T,I> {
-	fn initialize_primeset(prime: &u128) {
-	}
+    /**
+     * Genesis without a prime leaves the cipher to set_cipher_params.
+     * Otherwise the parameters are checked like new ones would be, and
+     * the accounts start unlocked, in the first era, with the balances
+     * encrypted off-chain under their keys.
+     */
+    fn initialize(config: &GenesisConfig<T,I>) {
+        let prime = config.primeset.0;
+        if prime == 0 {
+            assert!(config.accounts.is_empty(), "Genesis accounts need cipher parameters");
+            return;
+        }
+        assert!(config.range_bits <= T::RangeBits::get(), "Genesis range width above RangeBits");
+        RangeWidth::<I>::put(config.range_bits);
+        assert!(
+            Self::check_params(config.generator.0, prime, config.second_generator.0).is_ok(),
+            "Invalid genesis cipher parameters",
+        );
+        Cipher::<I>::put(CipherInfo(config.generator.0, prime));
+        SecondGenerator::<I>::put(config.second_generator.0);
+        for (who, pubkey, current) in config.accounts.iter() {
+            assert!(pubkey.0 > 1 && primering::in_subgroup(prime, pubkey.0), "Invalid genesis public key");
+            let balance = CipherText {
+                pubkey: pubkey.0,
+                rel: 0,
+                current: (current.0 .0, current.1 .0),
+                lock: UNLOCKED,
+            };
+            <BalanceMap<T,I>>::insert(who, balance);
+        }
+        ConfidentialIssuance::<I>::put(config.issuance.0);
+    }
+
+    fn current_epoch() -> T::BlockNumber {
+        let now = <system::Module<T>>::block_number();
+        now.checked_div(&T::EpochLength::get()).unwrap_or(now)
+    }
+
+    /**
+     * Generator g_e of this epoch's tags, hashed out of the genesis hash,
+     * the token instance and the epoch. Every proof spending from current
+     * shows the tag of its key under it, see proof/tag.rs.
+     */
+    pub fn epoch_generator(cipher: &EGICipher<u128>) -> u128 {
+        let genesis = <system::Module<T>>::block_hash(T::BlockNumber::zero());
+        tag::epoch_generator(cipher, &(genesis, I::PREFIX, Self::current_epoch()).encode())
+    }
+
+    /**
+     * A ring transfer debits its sender through pending, the tag it showed
+     * keeps the sender from spending current a second time in this epoch.
+     */
+    fn check_tag(tag: u128) -> Result<(), Error<T,I>> {
+        if <RingTags<T,I>>::contains_key(Self::current_epoch(), tag) {
+            Err(Error::<T,I>::TagSpent)
+        } else {
+            Ok(())
+        }
+    }
+
+    /* Tags of the epoch ending with block now can not be shown again */
+    fn clear_ring_tags(now: T::BlockNumber) {
+        let length = T::EpochLength::get();
+        if length.is_zero() || (now.saturating_add(One::one()) % length).is_zero() {
+            <RingTags<T,I>>::remove_prefix(Self::current_epoch());
+        }
+    }
+
+    /**
+     * Zether style roll over: the first operation of an account in a new
+     * epoch moves everything received so far from pending into current.
+     * Within an epoch current only changes by the owner's own operations,
+     * so proofs built against it cannot be invalidated by incoming funds.
+     * Calls check their proofs against balance_preview, which already
+     * includes the roll over, and only write it once the checks passed.
+     */
+    fn roll_over(cipher: &EGICipher<u128>, who: &T::AccountId) {
+        let epoch = Self::current_epoch();
+        if <LastRollOver<T,I>>::get(who) >= epoch {
+            return;
+        }
+        <LastRollOver<T,I>>::insert(who, epoch);
+        if let Some(pending) = <PendingMap<T,I>>::take(who) {
+            let balance = <BalanceMap<T,I>>::get(who).increase_encrypted(cipher, pending);
+            Self::deposit_event(RawEvent::RolledOver(who.clone(), balance.current));
+            <BalanceMap<T,I>>::insert(who, balance);
+        }
+    }
+
+    /**
+     * Incoming funds never touch current directly, they accumulate
+     * in the receiver's pending cipher until the next roll over.
+     */
+    fn credit_pending(cipher: &EGICipher<u128>, who: &T::AccountId, delta: (u128,u128)) {
+        let pending = match <PendingMap<T,I>>::get(who) {
+            Some(pending) => cipher.plus(pending, delta),
+            None => delta,
+        };
+        <PendingMap<T,I>>::insert(who, pending);
+    }
+
+    /**
+     * Verify the range proofs of all locks queued in this block with one
+     * random linear combination. If the batch fails, every proof is checked
+     * on its own, the bad ones are dropped and the others still apply.
+     * Proofs are checked against the balance at the end of the block, a
+     * lock whose balance changed after it was queued does not verify, nor
+     * does one whose tag a ring transfer spent after it was queued.
+     */
+    fn apply_locks() {
+        let queue = <LockQueue<T,I>>::take();
+        if queue.is_empty() {
+            return;
+        }
+        let cipher = match Self::cipher() {
+            Ok(cipher) => cipher,
+            Err(_) => {
+                for (who, _, _, _) in queue {
+                    Self::deposit_event(RawEvent::LockRejected(who));
+                }
+                return;
+            },
+        };
+        let width = Self::range_width();
+        let base = Self::epoch_generator(&cipher);
+        let mut equations = Vec::new();
+        let mut locks = Vec::new();
+        for (who, amount, nonce, proof) in queue {
+            /* An amount out of range would wrap around in the exponent */
+            if Self::check_amount(amount).is_err() {
+                Self::deposit_event(RawEvent::LockRejected(who));
+                continue;
+            }
+            let balance = match Self::balance_preview(&cipher, &who) {
+                Some(balance) => balance,
+                None => {
+                    Self::deposit_event(RawEvent::LockRejected(who));
+                    continue;
+                },
+            };
+            let delta = cipher.encode(balance.pubkey, amount, balance.rel);
+            let remain_cipher = cipher.minus(balance.current, delta);
+            let context = Self::proof_context(&who, nonce);
+            if Self::check_tag(proof.tag).is_ok()
+                && tag::tagged_equations(&cipher, base, balance.pubkey, remain_cipher, width, &context, &proof, &mut equations) {
+                locks.push((who, amount, balance, remain_cipher, context, proof));
+            } else {
+                Self::deposit_event(RawEvent::LockRejected(who));
+            }
+        }
+        let batch_ok = sigma::batch_verify(&cipher, &equations);
+        for (who, amount, balance, remain_cipher, context, proof) in locks {
+            if batch_ok || tag::tagged_range(&cipher, base, balance.pubkey, remain_cipher, width, &context, &proof) {
+                Self::roll_over(&cipher, &who);
+                let locked = balance.lock(&cipher, amount);
+                Self::deposit_event(RawEvent::BalanceLocked(who.clone(), locked.lock, locked.current));
+                <BalanceMap<T,I>>::insert(who, locked);
+            } else {
+                Self::deposit_event(RawEvent::LockRejected(who));
+            }
+        }
+    }
+
+    /**
+     * The cipher of this instance. Parameters that were never set up or
+     * can not carry range proofs of the configured width are rejected.
+     * While balances migrate to new parameters, nothing but the migration
+     * itself can use the cipher.
+     */
+    fn cipher() -> Result<EGICipher<u128>, Error<T,I>> {
+        if <CipherMigration<T,I>>::exists() {
+            return Err(Error::<T,I>::MigrationInProgress);
+        }
+        let cipher = Cipher::<I>::get().to_cipher();
+        if cipher.prime > 2 && cipher.gamma > 1 && cipher.gamma < cipher.prime
+            && cipher.range_fits(Self::range_width()) {
+            Ok(cipher)
+        } else {
+            Err(Error::<T,I>::BadParameters)
+        }
+    }
+
+    /**
+     * New parameters must form a cipher: p a safe prime, γ and h of the
+     * prime order q = (p - 1) / 2, so that they generate the group every
+     * proof works in, and that group large enough for range proofs of
+     * the configured width.
+     */
+    fn check_params(gamma: u128, prime: u128, h: u128) -> Result<(), Error<T,I>> {
+        let cipher = EGICipher {gamma:gamma, prime:prime};
+        if h != gamma
+            && primering::has_prime_order(prime, gamma)
+            && primering::has_prime_order(prime, h)
+            && cipher.range_fits(Self::range_width()) {
+            Ok(())
+        } else {
+            Err(Error::<T,I>::BadParameters)
+        }
+    }
+
+    /* Switch to the parameters of the migration once its deadline passed */
+    fn finish_migration(now: T::BlockNumber) {
+        match <CipherMigration<T,I>>::get() {
+            Some((info, h, deadline)) if now >= deadline => {
+                <CipherMigration<T,I>>::kill();
+                Self::deposit_event(RawEvent::CipherParamsChanged(info.0, info.1, h));
+                Cipher::<I>::put(info);
+                SecondGenerator::<I>::put(h);
+                CipherEra::<I>::mutate(|era| *era += 1);
+            },
+            _ => (),
+        }
+    }
+
+    /**
+     * The balance of who under the current parameters: the one in
+     * BalanceMap if it is of the current era, or the one who migrated to
+     * before the last switch. None for unknown or stale accounts.
+     */
+    fn settled_balance(who: &T::AccountId) -> Option<CipherText<u128>> {
+        if !<BalanceMap<T,I>>::contains_key(who) {
+            return None;
+        }
+        let era = CipherEra::<I>::get();
+        if <AccountEra<T,I>>::get(who) == era {
+            return Some(<BalanceMap<T,I>>::get(who));
+        }
+        match <MigratedBalance<T,I>>::get(who) {
+            Some((migrated, balance)) if migrated == era => Some(balance),
+            _ => None,
+        }
+    }
+
+    /**
+     * A public amount as the exponent it is encoded with, it has to be
+     * within range so that it can be proved about once encrypted.
+     */
+    fn confidential_amount(amount: BalanceOf<T,I>) -> Result<u128, Error<T,I>> {
+        Self::check_amount(amount.saturated_into::<u128>())
+    }
+
+    /* An amount in clear that is encoded into a cipher needs to be within range */
+    fn check_amount(value: u128) -> Result<u128, Error<T,I>> {
+        if value < 1u128 << Self::range_width() {
+            Ok(value)
+        } else {
+            Err(Error::<T,I>::AmountOutOfRange)
+        }
+    }
+
+    /**
+     * Value is conserved between the two pallets: the public issuance plus
+     * what is shielded stays the same over shield and unshield. Fees
+     * the minted supply can not cover lower it.
+     */
+    pub fn total_supply() -> BalanceOf<T,I> {
+        T::Currency::total_issuance().saturating_add(<ShieldedSupply<T,I>>::get())
+    }
+
+    /* Width of range proofs in bits, set at genesis or RangeBits if none was */
+    fn range_width() -> usize {
+        match RangeWidth::<I>::get() {
+            0 => T::RangeBits::get() as usize,
+            bits => bits as usize,
+        }
+    }
+
+    /* Hash of the parameters every proof envelope must be sealed with */
+    pub fn params_hash(cipher: &EGICipher<u128>) -> [u8; 32] {
+        envelope::params_hash(cipher.gamma, cipher.prime, SecondGenerator::<I>::get())
+    }
+
+    /**
+     * Every proof transcript starts with the genesis hash, the token
+     * instance, the account submitting the proof and its confidential
+     * nonce. A proof seen in the pool can neither be submitted by somebody
+     * else, nor on another chain or to another token of the same chain,
+     * nor again once the nonce moved on.
+     */
+    pub fn proof_context(who: &T::AccountId, nonce: u64) -> Vec<u8> {
+        let genesis = <system::Module<T>>::block_hash(T::BlockNumber::zero());
+        (genesis, I::PREFIX, who, nonce).encode()
+    }
+
+    /* Transcript context of who's next proof */
+    fn current_context(who: &T::AccountId) -> Vec<u8> {
+        Self::proof_context(who, <ConfidentialNonce<T,I>>::get(who))
+    }
+
+    /**
+     * Transcript context of a balance disclosure: the genesis hash, the
+     * token instance, the account and a challenge picked by the auditor. A
+     * disclosure made to one auditor can not be passed on as the answer to
+     * another challenge.
+     */
+    pub fn disclosure_context(who: &T::AccountId, challenge: &[u8]) -> Vec<u8> {
+        let genesis = <system::Module<T>>::block_hash(T::BlockNumber::zero());
+        (genesis, b"disclose", I::PREFIX, who, challenge).encode()
+    }
+
+    /**
+     * Check what who discloses about its current balance, false for an
+     * unknown account. Pending funds are not part of the current balance
+     * until the account rolls over.
+     */
+    pub fn verify_disclosure(who: &T::AccountId, challenge: &[u8], disclosure: &Disclosure<u128>) -> bool {
+        let (cipher, balance) = match (Self::cipher(), Self::settled_balance(who)) {
+            (Ok(cipher), Some(balance)) => (cipher, balance),
+            _ => return false,
+        };
+        cipher.disclose(
+            balance.pubkey,
+            balance.current,
+            Self::range_width(),
+            &Self::disclosure_context(who, challenge),
+            disclosure,
+        )
+    }
+
+    /**
+     * Every extrinsic that checked proofs of who moves its nonce on, the
+     * next nonce is taken up front and written once every check passed.
+     */
+    fn next_nonce(who: &T::AccountId) -> Result<u64, Error<T,I>> {
+        <ConfidentialNonce<T,I>>::get(who).checked_add(1).ok_or(Error::<T,I>::Overflow)
+    }
+
+    /**
+     * Accounts without a confidential balance can neither send nor receive,
+     * nor can those still under parameters of an earlier era. A balance
+     * migrated before the last switch replaces the stale one here. Its
+     * pending was folded in by migrate_balance and nothing could credit it
+     * before the switch, what is pending now came after and stays.
+     */
+    fn ensure_account(who: &T::AccountId) -> Result<(), Error<T,I>> {
+        if !<BalanceMap<T,I>>::contains_key(who) {
+            return Err(Error::<T,I>::UnknownAccount);
+        }
+        let era = CipherEra::<I>::get();
+        if <AccountEra<T,I>>::get(who) == era {
+            return Ok(());
+        }
+        match Self::settled_balance(who) {
+            Some(balance) => {
+                <MigratedBalance<T,I>>::remove(who);
+                <AccountEra<T,I>>::insert(who, era);
+                <BalanceMap<T,I>>::insert(who, balance);
+                Ok(())
+            },
+            None => Err(Error::<T,I>::StaleCipher),
+        }
+    }
+
+    /**
+     * Check the envelope's version, parameters and that it was built for
+     * the nonce expected, return the scheme tag and body. Extrinsics expect
+     * the sender's current nonce, the pool may check against a later one.
+     */
+    fn unseal<'a>(
+        cipher: &EGICipher<u128>,
+        expected: u64,
+        proof: &'a ProofEnvelope,
+    ) -> Result<(u16, &'a [u8]), Error<T,I>> {
+        let (scheme, nonce, body) = proof.unseal(&Self::params_hash(cipher))?;
+        if nonce < expected {
+            Err(Error::<T,I>::StaleNonce)
+        } else if nonce > expected {
+            Err(Error::<T,I>::FutureNonce)
+        } else {
+            Ok((scheme, body))
+        }
+    }
+
+    /**
+     * Proof envelopes are dispatched on their scheme tag, a statement
+     * only accepts the schemes that can prove it.
+     */
+    fn open_range(
+        cipher: &EGICipher<u128>,
+        nonce: u64,
+        proof: &ProofEnvelope,
+    ) -> Result<RangeProof<u128>, Error<T,I>> {
+        let range: RangeProof<u128> = match Self::unseal(cipher, nonce, proof)? {
+            (SCHEME_SIGMA_RANGE, body) => decode_body(body)?,
+            _ => return Err(EnvelopeError::WrongScheme.into()),
+        };
+        if range.bits.len() != Self::range_width() {
+            return Err(Error::<T,I>::RangeWidthMismatch);
+        }
+        Ok(range)
+    }
+
+    /* What remains of current after a debit, tagged with the epoch tag of the key */
+    fn open_remain(
+        cipher: &EGICipher<u128>,
+        nonce: u64,
+        proof: &ProofEnvelope,
+    ) -> Result<TaggedRange<u128>, Error<T,I>> {
+        let remain: TaggedRange<u128> = match Self::unseal(cipher, nonce, proof)? {
+            (SCHEME_TAGGED_RANGE, body) => decode_body(body)?,
+            _ => return Err(EnvelopeError::WrongScheme.into()),
+        };
+        if remain.range.bits.len() != Self::range_width() {
+            return Err(Error::<T,I>::RangeWidthMismatch);
+        }
+        Ok(remain)
+    }
+
+    fn open_ring(
+        cipher: &EGICipher<u128>,
+        nonce: u64,
+        proof: &ProofEnvelope,
+    ) -> Result<AnonymousTransfer<u128>, Error<T,I>> {
+        match Self::unseal(cipher, nonce, proof)? {
+            (SCHEME_RING, body) => Ok(decode_body(body)?),
+            _ => Err(EnvelopeError::WrongScheme.into()),
+        }
+    }
+
+    /* A proof of who bound to nonce, target under key is within range */
+    fn verify_range(
+        cipher: &EGICipher<u128>,
+        who: &T::AccountId,
+        nonce: u64,
+        key: u128,
+        target: (u128,u128),
+        proof: &ProofEnvelope,
+    ) -> Result<(), Error<T,I>> {
+        let range = Self::open_range(cipher, nonce, proof)?;
+        if cipher.within_exp(key, target, Self::range_width(), &Self::proof_context(who, nonce), &range) {
+            Ok(())
+        } else {
+            Err(Error::<T,I>::InvalidProof)
+        }
+    }
+
+    /**
+     * A proof of who bound to nonce, what remains of current under key is
+     * within range. Its tag must not have been spent in this epoch.
+     */
+    fn verify_remain(
+        cipher: &EGICipher<u128>,
+        who: &T::AccountId,
+        nonce: u64,
+        key: u128,
+        target: (u128,u128),
+        proof: &ProofEnvelope,
+    ) -> Result<(), Error<T,I>> {
+        let remain = Self::open_remain(cipher, nonce, proof)?;
+        Self::check_tag(remain.tag)?;
+        let base = Self::epoch_generator(cipher);
+        if tag::tagged_range(cipher, base, key, target, Self::range_width(), &Self::proof_context(who, nonce), &remain) {
+            Ok(())
+        } else {
+            Err(Error::<T,I>::InvalidProof)
+        }
+    }
+
+    fn verify_equality(
+        cipher: &EGICipher<u128>,
+        who: &T::AccountId,
+        nonce: u64,
+        src_key: u128,
+        src: (u128,u128),
+        dest_key: u128,
+        dest: (u128,u128),
+        proof: &ProofEnvelope,
+    ) -> Result<(), Error<T,I>> {
+        let valid = match Self::unseal(cipher, nonce, proof)? {
+            (SCHEME_EQUALITY, body) => {
+                let equality: EqualityProof<u128> = decode_body(body)?;
+                cipher.equal_amount(src_key, src, dest_key, dest, &Self::proof_context(who, nonce), &equality)
+            },
+            _ => return Err(EnvelopeError::WrongScheme.into()),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::<T,I>::InvalidProof)
+        }
+    }
+
+    /**
+     * Check that current under key encodes what balance holds, pending
+     * credits not rolled over yet included. The migration proof works in
+     * a single group as well, it re-encrypts the amount bit by bit.
+     */
+    fn check_rotation(
+        cipher: &EGICipher<u128>,
+        who: &T::AccountId,
+        nonce: u64,
+        balance: &CipherText<u128>,
+        key: u128,
+        current: (u128,u128),
+        proof: &ProofEnvelope,
+    ) -> Result<(), Error<T,I>> {
+        if balance.lock != UNLOCKED {
+            return Err(Error::<T,I>::LockMismatch);
+        }
+        if key == 1 || !primering::in_subgroup(cipher.prime, key) {
+            return Err(Error::<T,I>::BadParameters);
+        }
+        let old = match Self::unmerged_pending(who) {
+            Some(pending) => cipher.plus(balance.current, pending),
+            None => balance.current,
+        };
+        let rotation: MigrationProof<u128> = match Self::unseal(cipher, nonce, proof)? {
+            (SCHEME_MIGRATION, body) => decode_body(body)?,
+            _ => return Err(EnvelopeError::WrongScheme.into()),
+        };
+        if migration::migrated(
+            cipher, balance.pubkey, old,
+            cipher, key, current,
+            Self::range_width(), &Self::proof_context(who, nonce), &rotation,
+        ) {
+            Ok(())
+        } else {
+            Err(Error::<T,I>::InvalidProof)
+        }
+    }
+
+    /**
+     * Check that delta (a cipher under the owner's key) can be taken out of
+     * balance: both delta and what remains afterwards must be within range.
+     */
+    fn check_debit(
+        cipher: &EGICipher<u128>,
+        who: &T::AccountId,
+        nonce: u64,
+        balance: &CipherText<u128>,
+        delta: (u128,u128),
+        proof: &ProofEnvelope,
+        remain_proof: &ProofEnvelope,
+    ) -> Result<(), Error<T,I>> {
+        let remain_cipher = cipher.minus(balance.current, delta);
+        Self::verify_range(cipher, who, nonce, balance.pubkey, delta, proof)?;
+        Self::verify_remain(cipher, who, nonce, balance.pubkey, remain_cipher, remain_proof)
+    }
+
+    /**
+     * Move debit out of src's current and credit into dest's pending.
+     * The signed and the unsigned transfer share it, they only differ
+     * in how src authorises the call.
+     */
+    fn transfer_from(
+        cipher: &EGICipher<u128>,
+        src: T::AccountId,
+        dest: T::AccountId,
+        debit: (u128,u128),
+        credit: (u128,u128),
+        proof: &ProofEnvelope,
+        remain_proof: &ProofEnvelope,
+        eq_proof: &ProofEnvelope,
+    ) -> dispatch::DispatchResult {
+        Self::ensure_account(&src)?;
+        Self::ensure_account(&dest)?;
+        if src == dest {
+            return Err(Error::<T,I>::TransferToSelf.into());
+        }
+        let nonce = Self::next_nonce(&src)?;
+
+        let src_balance = Self::balance_preview(cipher, &src).ok_or(Error::<T,I>::UnknownAccount)?;
+        let dest_balance = <BalanceMap<T,I>>::get(dest.clone());
+        Self::check_debit(cipher, &src, Self::confidential_nonce(&src), &src_balance, debit, proof, remain_proof)?;
+        Self::verify_equality(cipher, &src, Self::confidential_nonce(&src), src_balance.pubkey, debit, dest_balance.pubkey, credit, eq_proof)?;
+
+        // once we reach this spot, no chance to raise exception
+        Self::roll_over(cipher, &src);
+        <ConfidentialNonce<T,I>>::insert(&src, nonce);
+        let src_new = src_balance.decrease_encrypted(cipher, debit);
+        Self::deposit_event(RawEvent::Transfer(src.clone(), dest.clone(), src_new.current, credit));
+        <BalanceMap<T,I>>::insert(src, src_new);
+        Self::credit_pending(cipher, &dest, credit);
+        Ok(())
+    }
+
+    /**
+     * Transcript context of the proof authorising an unsigned transfer:
+     * who's proof context and the rest of the call. The proof can not
+     * authorise a transfer to another account, nor of other ciphers.
+     */
+    pub fn authorisation_context(
+        who: &T::AccountId,
+        nonce: u64,
+        dest: &T::AccountId,
+        debit: (u128,u128),
+        credit: (u128,u128),
+        proof: &ProofEnvelope,
+        remain_proof: &ProofEnvelope,
+        eq_proof: &ProofEnvelope,
+    ) -> Vec<u8> {
+        (Self::proof_context(who, nonce), b"unsigned", dest, debit, credit, proof, remain_proof, eq_proof).encode()
+    }
+
+    /* Knowledge of the secret key of key, bound to nonce and context */
+    fn verify_authorisation(
+        cipher: &EGICipher<u128>,
+        nonce: u64,
+        key: u128,
+        context: &[u8],
+        proof: &ProofEnvelope,
+    ) -> Result<(), Error<T,I>> {
+        let valid = match Self::unseal(cipher, nonce, proof)? {
+            (SCHEME_KEY_OWNERSHIP, body) => {
+                let ownership: SigmaProof<u128> = decode_body(body)?;
+                let statement = sigma::DLog {prime:cipher.prime, base:cipher.gamma, target:key};
+                sigma::verify(cipher, &statement, context, &ownership)
+            },
+            _ => return Err(EnvelopeError::WrongScheme.into()),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::<T,I>::InvalidProof)
+        }
+    }
+
+    /* An account sends at most one unsigned transfer every UnsignedInterval blocks */
+    fn check_interval(who: &T::AccountId) -> Result<(), Error<T,I>> {
+        match <LastUnsigned<T,I>>::get(who) {
+            Some(last) if <system::Module<T>>::block_number() < last.saturating_add(T::UnsignedInterval::get()) => {
+                Err(Error::<T,I>::UnsignedTooSoon)
+            },
+            _ => Ok(()),
+        }
+    }
 }
 
 decl_storage! {
     trait Store for Module<T:Trait<I>, I:Instance = DefaultInstance>
     as Token {
-        pub ProofSetting build(|config: &GenesisConfig| {
-            config.primeset
+        pub ProofSetting build(|config: &GenesisConfig<T,I>| {
+            config.primeset.0
         }): u128;
 
         pub Rel: u32;
 
         pub Cipher: CipherInfo;
 
+        /* Bumped whenever the cipher parameters change */
+        pub CipherEra get(cipher_era): u32;
+
+        /* New parameters (γ, p), their h and the block from which on they apply */
+        pub CipherMigration: Option<(CipherInfo, u128, T::BlockNumber)>;
+
+        /* h for commitments γ^m * h^r, nobody knows log_γ(h) */
+        pub SecondGenerator: u128;
+
+        /* Width of range proofs when narrower than RangeBits, 0 if not */
+        pub RangeWidth: u32;
+
         BalanceMap get(balance_balance_getter):
             map hasher(blake2_256) T::AccountId => CipherText<u128>;
+
+        /* The era of the parameters the account's balance is encrypted under */
+        pub AccountEra get(account_era):
+            map hasher(blake2_256) T::AccountId => u32;
+
+        /* Balances re-encrypted for the era a scheduled migration leads to */
+        pub MigratedBalance get(migrated_balance):
+            map hasher(blake2_256) T::AccountId => Option<(u32, CipherText<u128>)>;
+
+        /* Incoming transfers not yet rolled into current */
+        pub PendingMap get(pending_getter):
+            map hasher(blake2_256) T::AccountId => Option<(u128,u128)>;
+
+        /* The epoch in which an account last rolled over */
+        pub LastRollOver:
+            map hasher(blake2_256) T::AccountId => T::BlockNumber;
+
+        /* lock_balance calls of this block with the nonce their proof is bound to,
+         * verified together in on_finalize */
+        pub LockQueue get(lock_queue): Vec<(T::AccountId, u128, u64, TaggedRange<u128>)>;
+
+        /* Epoch tags anonymous transfers showed, by epoch */
+        pub RingTags:
+            double_map hasher(blake2_256) T::BlockNumber, hasher(blake2_256) u128 => bool;
+
+        /* Confidential value created by mint and not yet destroyed by burn or fees */
+        pub ConfidentialIssuance get(confidential_issuance): u128;
+
+        /* Public value burnt by shield, not yet minted back by unshield nor paid as fees */
+        pub ShieldedSupply get(shielded_supply): BalanceOf<T,I>;
+
+        /* Bound into every proof transcript, moves on with each extrinsic carrying proofs */
+        pub ConfidentialNonce get(confidential_nonce):
+            map hasher(blake2_256) T::AccountId => u64;
+
+        /* The block of an account's last unsigned transfer */
+        pub LastUnsigned get(last_unsigned):
+            map hasher(blake2_256) T::AccountId => Option<T::BlockNumber>;
     }
 	add_extra_genesis {
-		config(primeset): u128;
-        build(|config| Module::<T,I>::initialize_primeset(&config.primeset))
+		config(primeset): BigInt;
+        /* γ, of prime order (p - 1) / 2 with p a safe prime */
+        config(generator): BigInt;
+        config(second_generator): BigInt;
+        config(range_bits): u32;
+        /* Account, public key and current balance encrypted under it */
+        config(accounts): Vec<(T::AccountId, BigInt, (BigInt, BigInt))>;
+        /* The sum of the balances of accounts */
+        config(issuance): BigInt;
+        build(|config| Module::<T,I>::initialize(config))
 	}
 
 }
@@ -109,35 +885,62 @@ decl_module! {
     pub struct Module<T: Trait<I>, I: Instance = DefaultInstance> for enum Call
     where origin: T::Origin {
 
+        type Error = Error<T,I>;
+
+        const EpochLength: T::BlockNumber = T::EpochLength::get();
+
+        const RingSize: u32 = T::RingSize::get();
+
+        const RangeBits: u32 = T::RangeBits::get();
+
+        const UnsignedInterval: T::BlockNumber = T::UnsignedInterval::get();
+
         fn deposit_event() = default;
 
+        fn on_finalize(n: T::BlockNumber) {
+            Self::apply_locks();
+            Self::clear_ring_tags(n);
+            Self::finish_migration(n);
+        }
+
         /**
          * Standard transfer function, release the locked amount
          * and transfer it into the recv's accout.
          */
+        #[weight = FunctionOf(
+            |_: (&u128, &<T::Lookup as StaticLookup>::Source)| weights::transfer(),
+            DispatchClass::Normal,
+            true
+        )]
         fn transfer(origin,
             amount:u128,
 			recv: <T::Lookup as StaticLookup>::Source
         ) -> dispatch::DispatchResult {
-            let cipher = Cipher::<I>::get().to_cipher();
+            let cipher = Self::cipher()?;
             let src = ensure_signed(origin)?;
-            let src_balance = <BalanceMap<T,I>>::get(src.clone());
+            Self::ensure_account(&src)?;
+            let amount = Self::check_amount(amount)?;
+            let src_balance = Self::balance_preview(&cipher, &src).ok_or(Error::<T,I>::UnknownAccount)?;
 			let dest = T::Lookup::lookup(recv)?;
 
             /*
              * Set the new balance for dest
              * Create an account if dest account does not exist.
              */
-            if !<BalanceMap<T,I>>::contains_key(dest.clone()) {
-                Err(DispatchError::Other("Account does not exists"))
+            if let Err(e) = Self::ensure_account(&dest) {
+                Err(e.into())
             } else {
-                let src_new = src_balance.release_locked(&cipher, amount)?;
+                let src_new = src_balance.clone().release_locked(&cipher, amount)
+                    .map_err(|_| Error::<T,I>::LockMismatch)?;
                 let dest_balance = <BalanceMap<T,I>>::get(dest.clone());
-                let dest_new = dest_balance.increase(&cipher, amount);
+                let delta = cipher.encode(dest_balance.pubkey, amount, dest_balance.rel);
 
                 // once we reach this spot, no chance to raise exception
+                Self::roll_over(&cipher, &src);
+                Self::deposit_event(RawEvent::LockReleased(src.clone(), src_balance.lock));
+                Self::deposit_event(RawEvent::Transfer(src.clone(), dest.clone(), src_new.current, delta));
                 <BalanceMap<T,I>>::insert(src, src_new);
-                <BalanceMap<T,I>>::insert(dest, dest_new);
+                Self::credit_pending(&cipher, &dest, delta);
                 Ok(())
             }
         }
@@ -183,51 +986,605 @@ decl_module! {
         /**
          * Before transfer, we need to lock enough balanced in
          * our account so that all the transfer transaction from
-         * a particular account is well ordered
+         * a particular account is well ordered.
+         *
+         * The lock is only queued here, its range proof is verified
+         * with all the others of the block in on_finalize and the
+         * lock takes effect there. The call fails right away for a proof
+         * that is not even of the right shape or whose tag is spent, the
+         * pool already dropped any that does not verify. The weight covers
+         * checking the proof on its own, should the batch fail.
          */
+        #[weight = FunctionOf(
+            |_: (&u128, &ProofEnvelope)| weights::lock_balance(T::RangeBits::get()),
+            DispatchClass::Normal,
+            true
+        )]
         fn lock_balance(
             origin,
             amount:u128,
-            s:u128,
-            proof:[(u128,u128);4],
-        ) {
+            proof:ProofEnvelope,
+        ) -> dispatch::DispatchResult {
             let who = ensure_signed(origin)?;
-            let cipher = Cipher::<I>::get().to_cipher();
-            let balance = <BalanceMap<T,I>>::get(who.clone());
-            let delta = cipher.encode(balance.pubkey, amount, balance.rel);
-            let remain_cipher = cipher.minus(balance.current, delta);
+            let cipher = Self::cipher()?;
+            Self::ensure_account(&who)?;
+            let amount = Self::check_amount(amount)?;
+            let nonce = <ConfidentialNonce<T,I>>::get(&who);
+            let proof = Self::open_remain(&cipher, nonce, &proof)?;
+            Self::check_tag(proof.tag)?;
+            if <LockQueue<T,I>>::get().iter().any(|(queued, _, _, _)| *queued == who) {
+                return Err(Error::<T,I>::LockQueued.into());
+            }
+            let balance = Self::balance_preview(&cipher, &who).ok_or(Error::<T,I>::UnknownAccount)?;
+            let remain = cipher.minus(balance.current, cipher.encode(balance.pubkey, amount, balance.rel));
+            let base = Self::epoch_generator(&cipher);
+            let context = Self::proof_context(&who, nonce);
+            if !tag::tagged_equations(&cipher, base, balance.pubkey, remain, Self::range_width(), &context, &proof, &mut Vec::new()) {
+                return Err(Error::<T,I>::MalformedProof.into());
+            }
+            let next = Self::next_nonce(&who)?;
 
-            /* TODO: need to port zkrp in ING
-             * Currently we assume the highest bit of one is less
-             * then 64, thus x < 2^64 - 1
-             */
-            cipher.within_exp(s, s, remain_cipher, proof.to_vec());
-            let who_new = balance.lock(&cipher, amount);
+            // once we reach this spot, no chance to raise exception
+            <ConfidentialNonce<T,I>>::insert(&who, next);
+            <LockQueue<T,I>>::mutate(|queue| queue.push((who, amount, nonce, proof)));
+            Ok(())
+        }
+
+        /**
+         * Confidential version of lock_balance. The amount to lock is
+         * a cipher under our own key, proved to be within range, and the
+         * balance that remains after the lock is proved to be within range
+         * as well. Nothing about the amount appears in clear.
+         */
+        #[weight = FunctionOf(
+            |_: (&(u128,u128), &ProofEnvelope, &ProofEnvelope)| weights::lock_encrypted(T::RangeBits::get()),
+            DispatchClass::Normal,
+            true
+        )]
+        fn lock_encrypted(
+            origin,
+            delta:(u128,u128),
+            proof:ProofEnvelope,
+            remain_proof:ProofEnvelope,
+        ) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            let cipher = Self::cipher()?;
+            Self::ensure_account(&who)?;
+            let nonce = Self::next_nonce(&who)?;
+            let balance = Self::balance_preview(&cipher, &who).ok_or(Error::<T,I>::UnknownAccount)?;
+
+            Self::check_debit(&cipher, &who, Self::confidential_nonce(&who), &balance, delta, &proof, &remain_proof)?;
+
+            // once we reach this spot, no chance to raise exception
+            Self::roll_over(&cipher, &who);
+            let who_new = balance.lock_encrypted(&cipher, delta);
+            <ConfidentialNonce<T,I>>::insert(&who, nonce);
+            Self::deposit_event(RawEvent::BalanceLocked(who.clone(), who_new.lock, who_new.current));
             <BalanceMap<T,I>>::insert(who, who_new);
+            Ok(())
+        }
+
+        /**
+         * Transfer everything locked by lock_encrypted to recv. The amount
+         * is credited as a cipher under the recv's key, together with a
+         * proof that it hides the same amount as our lock.
+         */
+        #[weight = FunctionOf(
+            |_: (&<T::Lookup as StaticLookup>::Source, &(u128,u128), &ProofEnvelope)| weights::transfer_encrypted(),
+            DispatchClass::Normal,
+            true
+        )]
+        fn transfer_encrypted(origin,
+            recv: <T::Lookup as StaticLookup>::Source,
+            credit:(u128,u128),
+            proof:ProofEnvelope,
+        ) -> dispatch::DispatchResult {
+            let cipher = Self::cipher()?;
+            let src = ensure_signed(origin)?;
+            Self::ensure_account(&src)?;
+            let nonce = Self::next_nonce(&src)?;
+            let src_balance = Self::balance_preview(&cipher, &src).ok_or(Error::<T,I>::UnknownAccount)?;
+            let dest = T::Lookup::lookup(recv)?;
+
+            if let Err(e) = Self::ensure_account(&dest) {
+                Err(e.into())
+            } else {
+                let dest_balance = <BalanceMap<T,I>>::get(dest.clone());
+                let src_pubkey = src_balance.pubkey;
+                let (src_new, released) = src_balance.release_encrypted(&cipher)
+                    .map_err(|_| Error::<T,I>::LockMismatch)?;
+                Self::verify_equality(&cipher, &src, Self::confidential_nonce(&src), src_pubkey, released, dest_balance.pubkey, credit, &proof)?;
+
+                // once we reach this spot, no chance to raise exception
+                Self::roll_over(&cipher, &src);
+                <ConfidentialNonce<T,I>>::insert(&src, nonce);
+                Self::deposit_event(RawEvent::LockReleased(src.clone(), released));
+                Self::deposit_event(RawEvent::Transfer(src.clone(), dest.clone(), src_new.current, credit));
+                <BalanceMap<T,I>>::insert(src, src_new);
+                Self::credit_pending(&cipher, &dest, credit);
+                Ok(())
+            }
+        }
+
+        /**
+         * Single step confidential transfer. The debit (under our key) and
+         * the credit (under the recv's key) are proved to hide the same
+         * amount, and the debit is range checked against the current balance,
+         * so no lock is needed in between.
+         *
+         * Both balances are read and written within this call, extrinsics
+         * touching the same account are therefore applied one after another
+         * in block order and each proof is checked against the balance left
+         * by the previous one. The credit goes to the recv's pending cipher.
+         */
+        #[weight = FunctionOf(
+            |_: (
+                &<T::Lookup as StaticLookup>::Source,
+                &(u128,u128), &(u128,u128),
+                &ProofEnvelope, &ProofEnvelope, &ProofEnvelope,
+            )| weights::confidential_transfer(T::RangeBits::get()),
+            DispatchClass::Normal,
+            true
+        )]
+        fn confidential_transfer(origin,
+            recv: <T::Lookup as StaticLookup>::Source,
+            debit:(u128,u128),
+            credit:(u128,u128),
+            proof:ProofEnvelope,
+            remain_proof:ProofEnvelope,
+            eq_proof:ProofEnvelope,
+        ) -> dispatch::DispatchResult {
+            let cipher = Self::cipher()?;
+            let src = ensure_signed(origin)?;
+            let dest = T::Lookup::lookup(recv)?;
+            Self::transfer_from(&cipher, src, dest, debit, credit, &proof, &remain_proof, &eq_proof)
+        }
+
+        /**
+         * confidential_transfer without a signer, so that no public key
+         * is tied to the sender. Instead of a signature, authorisation
+         * proves knowledge of the secret key of src's balance over the
+         * rest of the call. It pays no fee: the pool gives it a low
+         * priority and an account can send one every UnsignedInterval
+         * blocks.
+         */
+        #[weight = FunctionOf(
+            |_: (
+                &<T::Lookup as StaticLookup>::Source,
+                &<T::Lookup as StaticLookup>::Source,
+                &(u128,u128), &(u128,u128),
+                &ProofEnvelope, &ProofEnvelope, &ProofEnvelope, &ProofEnvelope,
+            )| weights::unsigned_transfer(T::RangeBits::get()),
+            DispatchClass::Normal,
+            false
+        )]
+        fn unsigned_transfer(origin,
+            src: <T::Lookup as StaticLookup>::Source,
+            recv: <T::Lookup as StaticLookup>::Source,
+            debit:(u128,u128),
+            credit:(u128,u128),
+            proof:ProofEnvelope,
+            remain_proof:ProofEnvelope,
+            eq_proof:ProofEnvelope,
+            authorisation:ProofEnvelope,
+        ) -> dispatch::DispatchResult {
+            ensure_none(origin)?;
+            let cipher = Self::cipher()?;
+            let src = T::Lookup::lookup(src)?;
+            let dest = T::Lookup::lookup(recv)?;
+
+            Self::ensure_account(&src)?;
+            Self::check_interval(&src)?;
+            let context = Self::authorisation_context(
+                &src, Self::confidential_nonce(&src), &dest, debit, credit, &proof, &remain_proof, &eq_proof,
+            );
+            let key = <BalanceMap<T,I>>::get(&src).pubkey;
+            Self::verify_authorisation(&cipher, Self::confidential_nonce(&src), key, &context, &authorisation)?;
+            Self::transfer_from(&cipher, src.clone(), dest, debit, credit, &proof, &remain_proof, &eq_proof)?;
+            <LastUnsigned<T,I>>::insert(src, <system::Module<T>>::block_number());
+            Ok(())
+        }
+
+        /**
+         * Anonymous transfer within a ring of accounts. Every member
+         * receives a debit and a credit into pending, and the
+         * one-out-of-many proof shows that only one member (the sender)
+         * is debited, without telling which one. Current stays as it is,
+         * so proofs the other members built against it still hold.
+         *
+         * The proof shows the sender's epoch tag, which is spent from
+         * then on: until the epoch ends, the sender can neither send
+         * from a ring again nor spend anything else out of current.
+         *
+         * The origin only pays for the extrinsic, it does not need to
+         * be a member of the ring. The proof is bound to the origin's
+         * nonce, so it cannot be relayed twice.
+         */
+        #[weight = FunctionOf(
+            |_: (&Vec<<T::Lookup as StaticLookup>::Source>, &ProofEnvelope)| {
+                weights::anonymous_transfer(T::RingSize::get(), T::RangeBits::get())
+            },
+            DispatchClass::Normal,
+            true
+        )]
+        fn anonymous_transfer(origin,
+            ring: Vec<<T::Lookup as StaticLookup>::Source>,
+            transfer: ProofEnvelope,
+        ) -> dispatch::DispatchResult {
+            let relayer = ensure_signed(origin)?;
+            let cipher = Self::cipher()?;
+            let nonce = Self::next_nonce(&relayer)?;
+            let transfer = Self::open_ring(&cipher, Self::confidential_nonce(&relayer), &transfer)?;
+            Self::check_tag(transfer.tag)?;
+            if ring.len() != T::RingSize::get() as usize {
+                return Err(Error::<T,I>::InvalidRing.into());
+            }
+
+            let mut members = Vec::new();
+            for source in ring {
+                let who = T::Lookup::lookup(source)?;
+                Self::ensure_account(&who)?;
+                if members.contains(&who) {
+                    return Err(Error::<T,I>::InvalidRing.into());
+                }
+                members.push(who);
+            }
+
+            let balances = members.iter()
+                .map(|who| Self::balance_preview(&cipher, who).ok_or(Error::<T,I>::UnknownAccount))
+                .collect::<Result<Vec<_>, _>>()?;
+            let keys = balances.iter().map(|b| b.pubkey).collect::<Vec<_>>();
+            let currents = balances.iter().map(|b| b.current).collect::<Vec<_>>();
+            let context = Self::current_context(&relayer);
+            let base = Self::epoch_generator(&cipher);
+            if !cipher.anonymous_transfer(SecondGenerator::<I>::get(), base, &keys, &currents, Self::range_width(), &context, &transfer) {
+                return Err(Error::<T,I>::InvalidProof.into());
+            }
+
+            // once we reach this spot, no chance to raise exception
+            <ConfidentialNonce<T,I>>::insert(&relayer, nonce);
+            <RingTags<T,I>>::insert(Self::current_epoch(), transfer.tag, true);
+            let mut debits = Vec::new();
+            let mut credits = Vec::new();
+            for (i, who) in members.iter().enumerate() {
+                Self::roll_over(&cipher, who);
+                let debit = (transfer.debits[i], transfer.debit_rand);
+                let credit = (transfer.credits[i], transfer.credit_rand);
+                debits.push(debit);
+                credits.push(credit);
+                Self::credit_pending(&cipher, who, cipher.minus(credit, debit));
+            }
+            Self::deposit_event(RawEvent::AnonymousTransfer(members, debits, credits));
+            Ok(())
         }
 
+        /**
+         * Move amount of our public balance into the confidential one. The
+         * public amount is burnt and credited, encrypted with randomness
+         * zero as it is public anyway, into pending.
+         */
+        #[weight = FunctionOf(
+            |_: (&BalanceOf<T,I>,)| weights::shield(),
+            DispatchClass::Normal,
+            true
+        )]
+        fn shield(origin, amount: BalanceOf<T,I>) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            let cipher = Self::cipher()?;
+            Self::ensure_account(&who)?;
+            let value = Self::confidential_amount(amount)?;
+            let supply = <ShieldedSupply<T,I>>::get().checked_add(&amount)
+                .ok_or(Error::<T,I>::Overflow)?;
+            let _ = T::Currency::withdraw(
+                &who,
+                amount,
+                WithdrawReason::Transfer.into(),
+                ExistenceRequirement::KeepAlive,
+            )?;
+
+            // once we reach this spot, no chance to raise exception
+            let credit = cipher.encode(<BalanceMap<T,I>>::get(&who).pubkey, value, 0);
+            <ShieldedSupply<T,I>>::put(supply);
+            Self::credit_pending(&cipher, &who, credit);
+            Self::deposit_event(RawEvent::Shielded(who, amount, credit));
+            Ok(())
+        }
+
+        /**
+         * Move amount out of our current confidential balance into the
+         * public one. The amount is public, the proof shows that what
+         * remains of current is within range.
+         */
+        #[weight = FunctionOf(
+            |_: (&BalanceOf<T,I>, &ProofEnvelope)| weights::unshield(T::RangeBits::get()),
+            DispatchClass::Normal,
+            true
+        )]
+        fn unshield(origin, amount: BalanceOf<T,I>, proof: ProofEnvelope) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            let cipher = Self::cipher()?;
+            Self::ensure_account(&who)?;
+            let value = Self::confidential_amount(amount)?;
+            /* only what was shielded can come back, whatever else encrypted value exists */
+            let supply = <ShieldedSupply<T,I>>::get().checked_sub(&amount)
+                .ok_or(Error::<T,I>::InsufficientShielded)?;
+            let nonce = Self::next_nonce(&who)?;
+            let balance = Self::balance_preview(&cipher, &who).ok_or(Error::<T,I>::UnknownAccount)?;
+            let debit = cipher.encode(balance.pubkey, value, 0);
+            let remain = cipher.minus(balance.current, debit);
+            Self::verify_remain(&cipher, &who, Self::confidential_nonce(&who), balance.pubkey, remain, &proof)?;
+
+            // once we reach this spot, no chance to raise exception
+            Self::roll_over(&cipher, &who);
+            <ConfidentialNonce<T,I>>::insert(&who, nonce);
+            let who_new = balance.decrease_encrypted(&cipher, debit);
+            <ShieldedSupply<T,I>>::put(supply);
+            let _ = T::Currency::deposit_creating(&who, amount);
+            Self::deposit_event(RawEvent::Unshielded(who.clone(), amount, who_new.current));
+            <BalanceMap<T,I>>::insert(who, who_new);
+            Ok(())
+        }
+
+        /**
+         * Create amount of confidential value for who, credited into its
+         * pending cipher. Only root (or whatever governance the runtime
+         * routes to root) can mint.
+         */
+        #[weight = FunctionOf(
+            |_: (&<T::Lookup as StaticLookup>::Source, &u128)| weights::mint(),
+            DispatchClass::Operational,
+            true
+        )]
+        fn mint(origin,
+            recv: <T::Lookup as StaticLookup>::Source,
+            amount: u128,
+        ) -> dispatch::DispatchResult {
+            ensure_root(origin)?;
+            let cipher = Self::cipher()?;
+            let who = T::Lookup::lookup(recv)?;
+            Self::ensure_account(&who)?;
+            let value = Self::check_amount(amount)?;
+            let issuance = <ConfidentialIssuance<I>>::get().checked_add(value)
+                .ok_or(Error::<T,I>::Overflow)?;
+
+            // once we reach this spot, no chance to raise exception
+            let credit = cipher.encode(<BalanceMap<T,I>>::get(&who).pubkey, value, 0);
+            <ConfidentialIssuance<I>>::put(issuance);
+            Self::credit_pending(&cipher, &who, credit);
+            Self::deposit_event(RawEvent::Minted(who, amount, credit));
+            Ok(())
+        }
+
+        /**
+         * Destroy amount of who's current balance. Root can not prove
+         * anything about somebody else's balance, the range proof of what
+         * remains comes from who and is bound to its nonce, as for unshield.
+         * That proof is who's consent to the burn: root alone can not burn
+         * anything, and one proof burns once.
+         */
+        #[weight = FunctionOf(
+            |_: (&<T::Lookup as StaticLookup>::Source, &u128, &ProofEnvelope)| {
+                weights::burn(T::RangeBits::get())
+            },
+            DispatchClass::Operational,
+            true
+        )]
+        fn burn(origin,
+            from: <T::Lookup as StaticLookup>::Source,
+            amount: u128,
+            proof: ProofEnvelope,
+        ) -> dispatch::DispatchResult {
+            ensure_root(origin)?;
+            let cipher = Self::cipher()?;
+            let who = T::Lookup::lookup(from)?;
+            Self::ensure_account(&who)?;
+            let value = Self::check_amount(amount)?;
+            let issuance = <ConfidentialIssuance<I>>::get().checked_sub(value)
+                .ok_or(Error::<T,I>::Overflow)?;
+            let nonce = Self::next_nonce(&who)?;
+            let balance = Self::balance_preview(&cipher, &who).ok_or(Error::<T,I>::UnknownAccount)?;
+            let debit = cipher.encode(balance.pubkey, value, 0);
+            let remain = cipher.minus(balance.current, debit);
+            Self::verify_remain(&cipher, &who, Self::confidential_nonce(&who), balance.pubkey, remain, &proof)?;
+
+            // once we reach this spot, no chance to raise exception
+            Self::roll_over(&cipher, &who);
+            <ConfidentialNonce<T,I>>::insert(&who, nonce);
+            <ConfidentialIssuance<I>>::put(issuance);
+            let who_new = balance.decrease_encrypted(&cipher, debit);
+            Self::deposit_event(RawEvent::Burnt(who.clone(), amount, who_new.current));
+            <BalanceMap<T,I>>::insert(who, who_new);
+            Ok(())
+        }
+
+        /**
+         * Overwrite our own balance with any amount. It mints out of thin
+         * air, so it only exists in runtimes built with the dev feature,
+         * everywhere else it fails.
+         */
+        #[weight = FunctionOf(
+            |_: (&u128,)| weights::reset_balance(),
+            DispatchClass::Normal,
+            true
+        )]
         fn reset_balance(
             origin,
             amount:u128,
         ) {
+            if !cfg!(feature = "dev") {
+                return Err(Error::<T,I>::DevOnly.into());
+            }
             let who = ensure_signed(origin)?;
-            let cipher = Cipher::<I>::get().to_cipher();
-            let who_balance = <BalanceMap<T,I>>::get(who.clone());
-            let who_new = who_balance.set(&cipher, amount);
+            let cipher = Self::cipher()?;
+
+            // once we reach this spot, no chance to raise exception
+            Self::roll_over(&cipher, &who);
+            let who_new = <BalanceMap<T,I>>::get(&who).set(&cipher, amount);
+            Self::deposit_event(RawEvent::BalanceReset(who.clone(), who_new.current));
             <BalanceMap<T,I>>::insert(who, who_new);
         }
 
+        /* Open our confidential account under key, rotate_key changes it later */
+        #[weight = FunctionOf(
+            |_: (&u128,)| weights::set_pubkey(),
+            DispatchClass::Normal,
+            true
+        )]
         fn set_pubkey(
             origin,
             key:u128,
         ) {
             let who = ensure_signed(origin)?;
-            let cipher = Cipher::<I>::get().to_cipher();
-            let who_balance = <BalanceMap<T,I>>::get(who.clone());
-            let who_new = who_balance.switch(&cipher, key);
+            let cipher = Self::cipher()?;
+            if <BalanceMap<T,I>>::contains_key(&who) {
+                return Err(Error::<T,I>::AccountExists.into());
+            }
+            /* a key outside the group of prime order could never prove anything */
+            if key == 1 || !primering::in_subgroup(cipher.prime, key) {
+                return Err(Error::<T,I>::BadParameters.into());
+            }
+
+            // once we reach this spot, no chance to raise exception
+            /* zero encoded with randomness zero is (1, 1), the unit credits add onto */
+            let who_new = CipherText::make(&cipher, key, 0, 0);
+            <AccountEra<T,I>>::insert(&who, CipherEra::<I>::get());
+            <LastRollOver<T,I>>::insert(&who, Self::current_epoch());
+            Self::deposit_event(RawEvent::AccountCreated(who.clone(), key, who_new.current));
             <BalanceMap<T,I>>::insert(who, who_new);
         }
 
+        /**
+         * Move our balance to key. current is the whole balance, pending
+         * credits included, re-encrypted under key and the proof shows
+         * both encode the same amount. The chain can not re-encrypt a
+         * cipher itself, so pending merges into current here whatever the
+         * epoch, credits left under the old key could never be decrypted.
+         */
+        #[weight = FunctionOf(
+            |_: (&u128, &(u128,u128), &ProofEnvelope)| weights::rotate_key(T::RangeBits::get()),
+            DispatchClass::Normal,
+            true
+        )]
+        fn rotate_key(origin,
+            key: u128,
+            current: (u128,u128),
+            proof: ProofEnvelope,
+        ) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            let cipher = Self::cipher()?;
+            Self::ensure_account(&who)?;
+            let nonce = Self::next_nonce(&who)?;
+            let balance = Self::balance_preview(&cipher, &who).ok_or(Error::<T,I>::UnknownAccount)?;
+            Self::check_rotation(&cipher, &who, Self::confidential_nonce(&who), &balance, key, current, &proof)?;
+
+            // once we reach this spot, no chance to raise exception
+            <ConfidentialNonce<T,I>>::insert(&who, nonce);
+            <PendingMap<T,I>>::remove(&who);
+            <LastRollOver<T,I>>::insert(&who, Self::current_epoch());
+            let rotated = CipherText {pubkey:key, rel:0, current:current, lock:UNLOCKED};
+            Self::deposit_event(RawEvent::KeyRotated(who.clone(), key, current));
+            <BalanceMap<T,I>>::insert(who, rotated);
+            Ok(())
+        }
+
+        /**
+         * Schedule new cipher parameters. Until deadline every account
+         * re-encrypts its balance with migrate_balance and nothing else
+         * uses the cipher, from deadline on the new parameters apply and
+         * balances that were not migrated are rejected. On a chain whose
+         * cipher was never set up, the parameters apply right away.
+         */
+        #[weight = FunctionOf(
+            |_: (&u128, &u128, &u128, &T::BlockNumber)| weights::set_cipher_params(),
+            DispatchClass::Operational,
+            true
+        )]
+        fn set_cipher_params(origin,
+            gamma: u128,
+            prime: u128,
+            h: u128,
+            deadline: T::BlockNumber,
+        ) -> dispatch::DispatchResult {
+            ensure_root(origin)?;
+            if <CipherMigration<T,I>>::exists() {
+                return Err(Error::<T,I>::MigrationInProgress.into());
+            }
+            Self::check_params(gamma, prime, h)?;
+            if Cipher::<I>::get().1 == 0 {
+                Cipher::<I>::put(CipherInfo(gamma, prime));
+                SecondGenerator::<I>::put(h);
+                Self::deposit_event(RawEvent::CipherParamsChanged(gamma, prime, h));
+                return Ok(());
+            }
+            if deadline <= <system::Module<T>>::block_number() {
+                return Err(Error::<T,I>::BadParameters.into());
+            }
+            <CipherMigration<T,I>>::put((CipherInfo(gamma, prime), h, deadline));
+            Self::deposit_event(RawEvent::CipherParamsScheduled(gamma, prime, h, deadline));
+            Ok(())
+        }
+
+        /**
+         * Re-encrypt our balance, current and pending, under the scheduled
+         * parameters as current under pubkey, a key of the new group. The
+         * proof shows both encode the same amount. Nothing may be locked.
+         * Pending is emptied, no credit can reach it until the switch as
+         * every other call fails with MigrationInProgress.
+         */
+        #[weight = FunctionOf(
+            |_: (&u128, &(u128,u128), &ProofEnvelope)| weights::migrate_balance(T::RangeBits::get()),
+            DispatchClass::Normal,
+            true
+        )]
+        fn migrate_balance(origin,
+            pubkey: u128,
+            current: (u128,u128),
+            proof: ProofEnvelope,
+        ) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+            let (info, _, _) = <CipherMigration<T,I>>::get().ok_or(Error::<T,I>::NoMigration)?;
+            Self::ensure_account(&who)?;
+            let next_era = CipherEra::<I>::get() + 1;
+            if let Some((era, _)) = <MigratedBalance<T,I>>::get(&who) {
+                if era == next_era {
+                    return Err(Error::<T,I>::AlreadyMigrated.into());
+                }
+            }
+            let nonce = Self::next_nonce(&who)?;
+            let cipher = Cipher::<I>::get().to_cipher();
+            let next = info.to_cipher();
+            if pubkey == 1 || !primering::in_subgroup(next.prime, pubkey) {
+                return Err(Error::<T,I>::BadParameters.into());
+            }
+            let balance = <BalanceMap<T,I>>::get(&who);
+            if balance.lock != UNLOCKED {
+                return Err(Error::<T,I>::LockMismatch.into());
+            }
+            let old = match <PendingMap<T,I>>::get(&who) {
+                Some(pending) => cipher.plus(balance.current, pending),
+                None => balance.current,
+            };
+            let migration: MigrationProof<u128> = match Self::unseal(&cipher, Self::confidential_nonce(&who), &proof)? {
+                (SCHEME_MIGRATION, body) => decode_body(body)?,
+                _ => return Err(EnvelopeError::WrongScheme.into()),
+            };
+            if !migration::migrated(
+                &cipher, balance.pubkey, old,
+                &next, pubkey, current,
+                Self::range_width(), &Self::current_context(&who), &migration,
+            ) {
+                return Err(Error::<T,I>::InvalidProof.into());
+            }
+
+            // once we reach this spot, no chance to raise exception
+            <ConfidentialNonce<T,I>>::insert(&who, nonce);
+            <PendingMap<T,I>>::remove(&who);
+            let migrated = CipherText {pubkey:pubkey, rel:0, current:current, lock:UNLOCKED};
+            <MigratedBalance<T,I>>::insert(&who, (next_era, migrated));
+            Self::deposit_event(RawEvent::BalanceMigrated(who, pubkey, current));
+            Ok(())
+        }
+
         fn TestStorage(
             origin,
             amount:u32,
@@ -239,11 +1596,115 @@ decl_module! {
     }
 }
 
+decl_error! {
+    pub enum Error for Module<T: Trait<I>, I: Instance> {
+        /// The account has no confidential balance
+        UnknownAccount,
+        /// A proof does not verify against the statement
+        InvalidProof,
+        /// A range proof of another width than RangeBits
+        RangeWidthMismatch,
+        /// The proof envelope version or the proof scheme is retired
+        RetiredProof,
+        /// The proof scheme is unknown or does not prove the statement at hand
+        WrongProofScheme,
+        /// The proof was built for another group or other cipher parameters
+        WrongProofParams,
+        /// The proof body does not decode
+        MalformedProof,
+        /// The proof is bound to a nonce the account already used
+        StaleNonce,
+        /// The proof is bound to a nonce the account has not reached yet
+        FutureNonce,
+        /// The amount released does not match what is locked, or nothing is locked
+        LockMismatch,
+        /// The account already queued a lock in this block
+        LockQueued,
+        /// The cipher parameters are not set up or can not carry the range proofs
+        BadParameters,
+        /// A ring of the wrong size or with duplicate members
+        InvalidRing,
+        /// Sender and receiver are the same account
+        TransferToSelf,
+        /// A counter or supply of the pallet would overflow or underflow
+        Overflow,
+        /// A public amount that can not be proved about once encrypted
+        AmountOutOfRange,
+        /// Unshielding more than was ever shielded
+        InsufficientShielded,
+        /// The call only exists in runtimes built with the dev feature
+        DevOnly,
+        /// Balances are migrating to new cipher parameters
+        MigrationInProgress,
+        /// No migration to new cipher parameters is scheduled
+        NoMigration,
+        /// The account already migrated its balance
+        AlreadyMigrated,
+        /// The account's balance is under parameters that no longer apply
+        StaleCipher,
+        /// The account sent an unsigned transfer less than UnsignedInterval blocks ago
+        UnsignedTooSoon,
+        /// The account already has a key, rotate_key moves it to another
+        AccountExists,
+        /// A ring transfer spent the account's epoch tag, current is spent until the epoch ends
+        TagSpent,
+    }
+}
+
+impl<T: Trait<I>, I: Instance> From<EnvelopeError> for Error<T,I> {
+    fn from(e: EnvelopeError) -> Self {
+        match e {
+            EnvelopeError::RetiredVersion | EnvelopeError::RetiredScheme => Error::<T,I>::RetiredProof,
+            EnvelopeError::UnknownScheme | EnvelopeError::WrongScheme => Error::<T,I>::WrongProofScheme,
+            EnvelopeError::WrongGroup | EnvelopeError::WrongParams => Error::<T,I>::WrongProofParams,
+            EnvelopeError::Malformed => Error::<T,I>::MalformedProof,
+        }
+    }
+}
+
 decl_event!(
-	pub enum Event<T> where
+	pub enum Event<T, I: Instance = DefaultInstance> where
 		<T as system::Trait>::AccountId,
+		<T as system::Trait>::BlockNumber,
+		Balance = BalanceOf<T, I>,
 	{
-		TokenEvent(AccountId),
+		/// A confidential account was created with its public key and current cipher.
+		AccountCreated(AccountId, u128, (u128, u128)),
+		/// The account switched to a new public key, current is re-encrypted under it.
+		KeyRotated(AccountId, u128, (u128, u128)),
+		/// Pending credits were rolled into the account's current cipher.
+		RolledOver(AccountId, (u128, u128)),
+		/// A lock took effect, with the account's new lock and current ciphers.
+		BalanceLocked(AccountId, (u128, u128), (u128, u128)),
+		/// A lock queued by lock_balance was dropped, its range proof does not verify.
+		LockRejected(AccountId),
+		/// The account's lock was released, with the released cipher.
+		LockReleased(AccountId, (u128, u128)),
+		/// Transfer from the first account to the second, with the sender's new
+		/// current cipher and the credit under the receiver's key, added to its pending.
+		Transfer(AccountId, AccountId, (u128, u128), (u128, u128)),
+		/// Anonymous transfer within a ring, with the debit and the credit
+		/// added to pending of every member.
+		AnonymousTransfer(Vec<AccountId>, Vec<(u128, u128)>, Vec<(u128, u128)>),
+		/// The account's balance was overwritten, with its new current cipher.
+		BalanceReset(AccountId, (u128, u128)),
+		/// Root minted an amount, credited to the account's pending cipher.
+		Minted(AccountId, u128, (u128, u128)),
+		/// Root burnt an amount of the account's current, with the new current cipher.
+		Burnt(AccountId, u128, (u128, u128)),
+		/// The account paid a fee from its current, with the new current cipher.
+		FeePaid(AccountId, u128, (u128, u128)),
+		/// Public balance was burnt and credited to the account's pending cipher.
+		Shielded(AccountId, Balance, (u128, u128)),
+		/// Current was debited and the public balance minted, with the new current cipher.
+		Unshielded(AccountId, Balance, (u128, u128)),
+		/// New parameters (γ, p, h) were scheduled, balances migrate until the block.
+		CipherParamsScheduled(u128, u128, u128, BlockNumber),
+		/// The parameters (γ, p, h) apply from now on.
+		CipherParamsChanged(u128, u128, u128),
+		/// The account re-encrypted its balance under the scheduled parameters,
+		/// with its new public key and current cipher.
+		BalanceMigrated(AccountId, u128, (u128, u128)),
 	}
 );
 
@@ -0,0 +1,166 @@
+//
+// A mock runtime carrying two instances of the token, the default one and
+// Instance1, both shielding from the same public balances.
+//
+
+use sp_core::H256;
+use frame_support::{impl_outer_origin, impl_outer_event, impl_outer_dispatch, parameter_types, traits::Get, weights::Weight};
+use sp_runtime::{
+    traits::{BlakeTwo256, IdentityLookup, ConvertInto},
+    transaction_validity::TransactionPriority,
+    testing::Header,
+    Perbill,
+};
+
+use crate::{Module, Trait, GenesisConfig, CipherText, EGICipher, BigInt, Instance1};
+use crate::prover;
+
+mod token {
+    pub use crate::{Event, DefaultInstance, Instance1};
+}
+
+impl_outer_origin! {
+    pub enum Origin for Test {}
+}
+
+impl_outer_event! {
+    pub enum TestEvent for Test {
+        balances<T>,
+        token<T>,
+        token Instance1<T>,
+    }
+}
+
+impl_outer_dispatch! {
+    pub enum Call for Test where origin: Origin {
+        token::Token,
+        token::SecondToken,
+    }
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+
+parameter_types! {
+    pub const BlockHashCount: u64 = 250;
+    pub const MaximumBlockWeight: Weight = 1_000_000_000;
+    pub const MaximumBlockLength: u32 = 2 * 1024;
+    pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+    pub const ExistentialDeposit: u64 = 1;
+    pub const EpochLength: u64 = 10;
+    pub const RingSize: u32 = 4;
+    pub const RangeBits: u32 = 16;
+    pub const ConfidentialBaseFee: u128 = 1;
+    pub const ConfidentialByteFee: u128 = 0;
+    pub const UnsignedPriority: TransactionPriority = 0;
+    pub const UnsignedInterval: u64 = 10;
+}
+
+impl system::Trait for Test {
+    type Origin = Origin;
+    type Call = Call;
+    type Index = u64;
+    type BlockNumber = u64;
+    type Hash = H256;
+    type Hashing = BlakeTwo256;
+    type AccountId = u64;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Header = Header;
+    type Event = TestEvent;
+    type BlockHashCount = BlockHashCount;
+    type MaximumBlockWeight = MaximumBlockWeight;
+    type MaximumBlockLength = MaximumBlockLength;
+    type AvailableBlockRatio = AvailableBlockRatio;
+    type Version = ();
+    type ModuleToIndex = ();
+    type AccountData = balances::AccountData<u64>;
+    type OnNewAccount = ();
+    type OnKilledAccount = ();
+}
+
+impl balances::Trait for Test {
+    type Balance = u64;
+    type Event = TestEvent;
+    type DustRemoval = ();
+    type ExistentialDeposit = ExistentialDeposit;
+    type AccountStore = System;
+}
+
+impl Trait for Test {
+    type Balance = CipherText<u128>;
+    type Event = TestEvent;
+    type EpochLength = EpochLength;
+    type RingSize = RingSize;
+    type RangeBits = RangeBits;
+    type Currency = Balances;
+    type ConfidentialBaseFee = ConfidentialBaseFee;
+    type ConfidentialByteFee = ConfidentialByteFee;
+    type WeightToConfidentialFee = ConvertInto;
+    type UnsignedPriority = UnsignedPriority;
+    type UnsignedInterval = UnsignedInterval;
+}
+
+impl Trait<Instance1> for Test {
+    type Balance = CipherText<u128>;
+    type Event = TestEvent;
+    type EpochLength = EpochLength;
+    type RingSize = RingSize;
+    type RangeBits = RangeBits;
+    type Currency = Balances;
+    type ConfidentialBaseFee = ConfidentialBaseFee;
+    type ConfidentialByteFee = ConfidentialByteFee;
+    type WeightToConfidentialFee = ConvertInto;
+    type UnsignedPriority = UnsignedPriority;
+    type UnsignedInterval = UnsignedInterval;
+}
+
+pub type System = system::Module<Test>;
+pub type Balances = balances::Module<Test>;
+pub type Token = Module<Test>;
+pub type SecondToken = Module<Test, Instance1>;
+
+/* The largest safe prime below 2^31, both pairs of generators have its prime order (PRIME - 1) / 2 */
+pub const PRIME: u128 = 2147483579;
+pub const TOKEN_CIPHER: EGICipher<u128> = EGICipher { gamma: 7, prime: PRIME };
+pub const TOKEN_H: u128 = 48271;
+pub const SECOND_CIPHER: EGICipher<u128> = EGICipher { gamma: 16807, prime: PRIME };
+pub const SECOND_H: u128 = 69620;
+
+/* The secret key of account who, the same in both tokens */
+pub fn secret(who: u64) -> u128 {
+    1_000_003 * who as u128
+}
+
+fn genesis<I: crate::Instance>(
+    cipher: &EGICipher<u128>,
+    h: u128,
+    balances: &[(u64, u128)],
+) -> GenesisConfig<Test, I> {
+    GenesisConfig {
+        primeset: cipher.prime.into(),
+        generator: cipher.gamma.into(),
+        second_generator: h.into(),
+        range_bits: RangeBits::get(),
+        accounts: balances.iter().map(|&(who, value)| {
+            let (pubkey, current) = prover::genesis_account(cipher, secret(who), value, 5 + who as u128);
+            (who, pubkey.into(), (current.0.into(), current.1.into()))
+        }).collect::<Vec<(u64, BigInt, (BigInt, BigInt))>>(),
+        issuance: balances.iter().map(|&(_, value)| value).sum::<u128>().into(),
+    }
+}
+
+/**
+ * Accounts 1 and 2 hold 100 and 200 of Token, accounts 1 and 3 hold 50
+ * and 70 of SecondToken. Everyone holds 1000 of the public currency.
+ */
+pub fn new_test_ext() -> sp_io::TestExternalities {
+    let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+    balances::GenesisConfig::<Test> {
+        balances: vec![(1, 1000), (2, 1000), (3, 1000)],
+    }.assimilate_storage(&mut t).unwrap();
+    genesis::<crate::DefaultInstance>(&TOKEN_CIPHER, TOKEN_H, &[(1, 100), (2, 200)])
+        .assimilate_storage(&mut t).unwrap();
+    genesis::<Instance1>(&SECOND_CIPHER, SECOND_H, &[(1, 50), (3, 70)])
+        .assimilate_storage(&mut t).unwrap();
+    t.into()
+}
@@ -23,10 +23,11 @@ impl PrimeRing<u128> for u128{
     }
 
     fn minus(self:&Self, x:u128,y:u128) -> u128 {
-        let x256 = U256::from(x);
-        let y256 = U256::from(y);
-        let z = x256 - y256;
-        z.checked_rem(U256::from(*self)).unwrap().as_u128()
+        let m = U256::from(*self);
+        let x256 = U256::from(x) % m;
+        let y256 = U256::from(y) % m;
+        let z = x256 + m - y256;
+        z.checked_rem(m).unwrap().as_u128()
     }
 
 
@@ -90,6 +91,65 @@ impl PrimeRing<u128> for u128{
     }
 }
 
+/**
+ * Miller-Rabin with the first twelve primes as bases, deterministic
+ * for n < 3.3 * 10^24 which covers every modulus a u128 cipher can use
+ * without the products of PrimeRing overflowing.
+ */
+pub fn is_prime(n:u128) -> bool {
+    const BASES:[u128; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    for b in BASES.iter() {
+        if n % b == 0 {
+            return n == *b;
+        }
+    }
+    let mut d = n - 1;
+    let mut s = 0;
+    while d % 2 == 0 {
+        d /= 2;
+        s += 1;
+    }
+    'witness: for b in BASES.iter() {
+        let mut x = n.power(*b, d);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = n.mul(x, x);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/**
+ * p = 2q + 1 with q prime. The squares of Z_p* then form a subgroup of
+ * prime order q, every element of it but 1 generates it.
+ */
+pub fn is_safe_prime(p:u128) -> bool {
+    is_prime(p) && is_prime((p - 1) / 2)
+}
+
+/* The order q of the subgroup of squares of Z_p*, exponents live modulo q */
+pub fn subgroup_order(p:u128) -> u128 {
+    (p - 1) / 2
+}
+
+/* x is a square of Z_p* (Euler's criterion), an element of the subgroup of order q */
+pub fn in_subgroup(p:u128, x:u128) -> bool {
+    x > 0 && x < p && p.power(x, subgroup_order(p)) == 1
+}
+
+/* g generates the subgroup of prime order q of Z_p*, p a safe prime */
+pub fn has_prime_order(p:u128, g:u128) -> bool {
+    is_safe_prime(p) && g > 1 && in_subgroup(p, g)
+}
 
 
 /// tests for this module
@@ -102,6 +162,7 @@ mod tests {
         let p:u128 = 7;
         assert_eq!(p.plus(1,3), 4);
     }
+    #[test]
     fn op_tests_minus() {
         let p:u128 = 7;
         assert_eq!(p.minus(1,3), 5);
@@ -119,6 +180,25 @@ mod tests {
         assert_eq!(p.power(2,3), 1);
         assert_eq!(p.power(3,3), 6);
     }
+    #[test]
+    fn prime_and_generator() {
+        assert!(is_prime(2147483647));
+        assert!(!is_prime(2147483647 * 2147483647));
+        assert!(!is_prime(3215031751));
+        /* 2^31 - 1 is prime, but (2^31 - 2) / 2 = 3^2 * 7 * 11 * 31 * 151 * 331 is not */
+        assert!(!is_safe_prime(2147483647));
+        assert!(is_safe_prime(2147483579));
+        /* squares have order q = 1073741789, -1 and non-squares do not */
+        assert!(has_prime_order(2147483579, 4));
+        assert!(has_prime_order(2147483579, 2147483579.mul(7, 7)));
+        assert!(!has_prime_order(2147483579, 1));
+        assert!(!has_prime_order(2147483579, 2147483578));
+        assert!(!has_prime_order(2147483579, 2));
+        assert!(!has_prime_order(2147483647, 4));
+        assert!(!in_subgroup(2147483579, 0));
+        assert!(!in_subgroup(2147483579, 2147483579 + 4));
+    }
+
     #[test]
     fn op_tests_div() {
         let p:u128 = 7;
@@ -0,0 +1,85 @@
+use codec::{Encode, Decode};
+use sp_runtime::RuntimeDebug;
+
+use crate::cipher::{
+    EGICipher,
+    CipherFunctor,
+};
+use crate::primering::PrimeRing;
+use crate::proof::{
+    CipherProof,
+    RangeProof,
+};
+use crate::proof::sigma::{
+    self,
+    SigmaProof,
+    DLogEq,
+};
+
+//
+// Balance disclosure to an auditor.
+//
+// The owner of a cipher t = (γ^b * y^r, γ^r) under y = γ^x can show
+//  - b equals an amount a: with the secret key, t.0 / γ^a = t.1^x,
+//    which is DLogEq over the bases (γ, t.1),
+//  - b is at least a: t / (γ^a, 1) encodes b - a, a range proof over
+//    it shows b - a ∈ [0, 2^k).
+// Neither reveals x or r. An auditor checks a disclosure against the
+// cipher it reads from the state of a block of its choice.
+//
+
+/* What the owner of a cipher claims about the amount it encodes */
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum Disclosure<T> {
+    /* The amount is exactly amount */
+    Exact {
+        amount: T,
+        proof: SigmaProof<T>,
+    },
+    /* The amount is at least amount */
+    AtLeast {
+        amount: T,
+        proof: RangeProof<T>,
+    },
+}
+
+pub trait CipherDisclosure<K, F, T> {
+    /* The cipher t under key decrypts to amount, witnessed by the secret key */
+    fn decryption_statement(&self, key:K, t:T, amount:F) -> DLogEq<F>;
+    fn decrypts_to(&self, key:K, t:T, amount:F, context:&[u8], proof:&SigmaProof<F>) -> bool;
+    /* What t encodes above amount, it is within range iff t encodes at least amount */
+    fn threshold_target(&self, t:T, amount:F) -> T;
+    fn at_least(&self, key:K, t:T, amount:F, width:usize, context:&[u8], proof:&RangeProof<F>) -> bool;
+    fn disclose(&self, key:K, t:T, width:usize, context:&[u8], disclosure:&Disclosure<F>) -> bool;
+}
+
+impl CipherDisclosure<u128, u128, (u128,u128)> for EGICipher<u128> {
+    fn decryption_statement(&self, key:u128, t:(u128,u128), amount:u128) -> DLogEq<u128> {
+        let p = self.prime;
+        DLogEq {prime:p, bases:(self.gamma, t.1), targets:(key, p.div(t.0, p.power(self.gamma, amount)))}
+    }
+
+    fn decrypts_to(&self, key:u128, t:(u128,u128), amount:u128, context:&[u8], proof:&SigmaProof<u128>) -> bool {
+        sigma::verify(self, &self.decryption_statement(key, t, amount), context, proof)
+    }
+
+    fn threshold_target(&self, t:(u128,u128), amount:u128) -> (u128,u128) {
+        /* (γ^a, 1) is a encoded with randomness zero under any key */
+        self.minus(t, (self.prime.power(self.gamma, amount), 1))
+    }
+
+    /*
+     * The range only bounds b - a from above by 2^k, as every balance
+     * is proved within range itself, that does not constrain b further.
+     */
+    fn at_least(&self, key:u128, t:(u128,u128), amount:u128, width:usize, context:&[u8], proof:&RangeProof<u128>) -> bool {
+        self.within_exp(key, self.threshold_target(t, amount), width, context, proof)
+    }
+
+    fn disclose(&self, key:u128, t:(u128,u128), width:usize, context:&[u8], disclosure:&Disclosure<u128>) -> bool {
+        match disclosure {
+            Disclosure::Exact {amount, proof} => self.decrypts_to(key, t, *amount, context, proof),
+            Disclosure::AtLeast {amount, proof} => self.at_least(key, t, *amount, width, context, proof),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use codec::{Encode, Decode};
+use frame_support::{
+    dispatch::{Vec},
+};
+use sp_io::hashing::blake2_256;
+use sp_runtime::{
+    DispatchError,
+    RuntimeDebug,
+};
+
+//
+// Proofs travel in a versioned envelope so that the verifier can
+// change without breaking every client.
+//
+// The SCALE variant index is the envelope version. Within a version
+// the scheme tag names the proof system and the body is the SCALE
+// encoding of a proof of that scheme, the group id and the hash of
+// the parameters pin down what the proof was computed over. The nonce
+// is the confidential nonce of the submitting account the proof was
+// built for, so a stale proof is told apart from an invalid one.
+//
+// An envelope of an unknown version does not decode at all, an
+// unknown or retired scheme decodes and is rejected by the pallet
+// with an error of its own.
+//
+
+/* Retired: ElGamal over all of Z_p*, whose order p - 1 is not prime */
+pub const GROUP_ELGAMAL_ZP: u32 = 1;
+/* ElGamal over the subgroup of prime order (p - 1) / 2 of Z_p*, p a safe prime */
+pub const GROUP_ELGAMAL_QR: u32 = 2;
+
+/* Retired: four unweighted bits sharing one s, proved nothing */
+pub const SCHEME_LEGACY_BITS: u16 = 0;
+/* RangeProof on the sigma framework */
+pub const SCHEME_SIGMA_RANGE: u16 = 1;
+/* EqualityProof */
+pub const SCHEME_EQUALITY: u16 = 2;
+/* AnonymousTransfer over a ring */
+pub const SCHEME_RING: u16 = 3;
+/* MigrationProof re-encrypting a balance under new parameters */
+pub const SCHEME_MIGRATION: u16 = 4;
+/* SigmaProof of the secret key, authorising an unsigned call */
+pub const SCHEME_KEY_OWNERSHIP: u16 = 5;
+/* TaggedRange, what remains of current together with the epoch tag */
+pub const SCHEME_TAGGED_RANGE: u16 = 6;
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum ProofEnvelope {
+    /* The bare (s, bits) tuple used before envelopes, retired */
+    V0(u128, [(u128, u128); 4]),
+    /* Not bound to a nonce, retired */
+    V1 {
+        scheme: u16,
+        group: u32,
+        params: [u8; 32],
+        body: Vec<u8>,
+    },
+    V2 {
+        scheme: u16,
+        group: u32,
+        params: [u8; 32],
+        nonce: u64,
+        body: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum EnvelopeError {
+    RetiredVersion,
+    UnknownScheme,
+    RetiredScheme,
+    /* A known scheme, but it does not prove the statement at hand */
+    WrongScheme,
+    WrongGroup,
+    WrongParams,
+    Malformed,
+}
+
+impl EnvelopeError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EnvelopeError::RetiredVersion => "Retired proof envelope version",
+            EnvelopeError::UnknownScheme => "Unknown proof scheme",
+            EnvelopeError::RetiredScheme => "Retired proof scheme",
+            EnvelopeError::WrongScheme => "Proof scheme does not match the statement",
+            EnvelopeError::WrongGroup => "Proof built for another group",
+            EnvelopeError::WrongParams => "Proof built for other cipher parameters",
+            EnvelopeError::Malformed => "Malformed proof",
+        }
+    }
+}
+
+impl From<EnvelopeError> for DispatchError {
+    fn from(e: EnvelopeError) -> Self {
+        DispatchError::Other(e.as_str())
+    }
+}
+
+/**
+ * Hash of the parameters a proof is computed over: γ, p and the second
+ * generator h. Changing any of them invalidates all envelopes in flight.
+ */
+pub fn params_hash(gamma:u128, prime:u128, h:u128) -> [u8; 32] {
+    blake2_256(&(GROUP_ELGAMAL_QR, gamma, prime, h).encode())
+}
+
+impl ProofEnvelope {
+    /* Wrap a proof of scheme in the current version */
+    pub fn seal<P:Encode>(scheme:u16, params:[u8; 32], nonce:u64, proof:&P) -> Self {
+        ProofEnvelope::V2 {
+            scheme,
+            group: GROUP_ELGAMAL_QR,
+            params,
+            nonce,
+            body: proof.encode(),
+        }
+    }
+
+    /**
+     * Check version, group and parameters, and return the scheme tag and
+     * the nonce together with the body for the caller to dispatch on.
+     */
+    pub fn unseal(&self, params:&[u8; 32]) -> Result<(u16, u64, &[u8]), EnvelopeError> {
+        match self {
+            ProofEnvelope::V0(..) | ProofEnvelope::V1 {..} => Err(EnvelopeError::RetiredVersion),
+            ProofEnvelope::V2 {scheme, group, params: sealed, nonce, body} => {
+                match *scheme {
+                    SCHEME_LEGACY_BITS => return Err(EnvelopeError::RetiredScheme),
+                    SCHEME_SIGMA_RANGE | SCHEME_EQUALITY | SCHEME_RING | SCHEME_MIGRATION | SCHEME_KEY_OWNERSHIP
+                    | SCHEME_TAGGED_RANGE => (),
+                    _ => return Err(EnvelopeError::UnknownScheme),
+                }
+                if *group != GROUP_ELGAMAL_QR {
+                    Err(EnvelopeError::WrongGroup)
+                } else if sealed != params {
+                    Err(EnvelopeError::WrongParams)
+                } else {
+                    Ok((*scheme, *nonce, &body[..]))
+                }
+            },
+        }
+    }
+}
+
+/* Decode the whole body as P, trailing bytes are malformed too */
+pub fn decode_body<P:Decode>(body:&[u8]) -> Result<P, EnvelopeError> {
+    let mut input = body;
+    let proof = P::decode(&mut input).map_err(|_| EnvelopeError::Malformed)?;
+    if input.is_empty() {
+        Ok(proof)
+    } else {
+        Err(EnvelopeError::Malformed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_unseal() {
+        let params = params_hash(7, 2147483579, 11);
+        let sealed = ProofEnvelope::seal(SCHEME_EQUALITY, params, 3, &(1u128, 2u128));
+        let decoded = ProofEnvelope::decode(&mut &sealed.encode()[..]).unwrap();
+        let (scheme, nonce, body) = decoded.unseal(&params).unwrap();
+        assert_eq!((scheme, nonce), (SCHEME_EQUALITY, 3));
+        assert_eq!(decode_body::<(u128, u128)>(body), Ok((1, 2)));
+        assert_eq!(decode_body::<u128>(body), Err(EnvelopeError::Malformed));
+
+        assert_eq!(sealed.unseal(&params_hash(7, 2147483579, 13)), Err(EnvelopeError::WrongParams));
+        assert_eq!(ProofEnvelope::V0(0, [(1, 1); 4]).unseal(&params), Err(EnvelopeError::RetiredVersion));
+        let unbound = ProofEnvelope::V1 { scheme: SCHEME_EQUALITY, group: GROUP_ELGAMAL_ZP, params, body: Vec::new() };
+        assert_eq!(unbound.unseal(&params), Err(EnvelopeError::RetiredVersion));
+        let retired = ProofEnvelope::seal(SCHEME_LEGACY_BITS, params, 0, &0u128);
+        assert_eq!(retired.unseal(&params), Err(EnvelopeError::RetiredScheme));
+        let unknown = ProofEnvelope::seal(42, params, 0, &0u128);
+        assert_eq!(unknown.unseal(&params), Err(EnvelopeError::UnknownScheme));
+        let composite = ProofEnvelope::V2 { scheme: SCHEME_EQUALITY, group: GROUP_ELGAMAL_ZP, params, nonce: 0, body: Vec::new() };
+        assert_eq!(composite.unseal(&params), Err(EnvelopeError::WrongGroup));
+    }
+}
@@ -0,0 +1,106 @@
+use codec::{Encode, Decode};
+use frame_support::{
+    dispatch::{Vec},
+};
+use sp_runtime::RuntimeDebug;
+
+use crate::cipher::{
+    EGICipher,
+    CipherFunctor,
+};
+use crate::primering::PrimeRing;
+use crate::proof::CipherProof;
+use crate::proof::sigma::{
+    self,
+    SigmaProof,
+    DLogEq,
+    And,
+    Or,
+    All,
+};
+
+//
+// Re-encryption of a balance under new cipher parameters.
+//
+// The owner splits the amount b into bits and encodes every bit twice,
+// B_j under the old key y in the old group and B'_j under the new key
+// y' in the new group. The sigma proof shows
+//  - for every j, B_j and B'_j both encode zero or both encode one,
+//  - old / Π_j B_j^(2^j) encodes zero, with the old secret key,
+//  - new / Π_j B'_j^(2^j) encodes zero, with the new randomness.
+// The two groups only share the bits, every witness lives in one group.
+// The challenge is derived in the old group, each side answers it in
+// its own group order. It lies below the order q of the old group, so on
+// the new side two distinct challenges only fall together when the new
+// order q' divides their difference, which a prover hits with chance
+// about 1/q'.
+//
+
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct MigrationProof<T> {
+    pub old_bits: Vec<(T, T)>,
+    pub new_bits: Vec<(T, T)>,
+    pub proof: SigmaProof<T>,
+}
+
+/* B_j and B'_j encode the same bit */
+pub type PairStatement<T> = Or<And<DLogEq<T>, DLogEq<T>>, And<DLogEq<T>, DLogEq<T>>>;
+
+pub type MigrationStatement<T> = And<All<PairStatement<T>>, And<DLogEq<T>, DLogEq<T>>>;
+
+/* target / Π_j bits_j^(2^j) */
+fn rest(cipher:&EGICipher<u128>, target:(u128,u128), bits:&[(u128,u128)]) -> (u128,u128) {
+    let p = cipher.prime;
+    let mut weighted = (1, 1);
+    for (j, bit) in bits.iter().enumerate() {
+        let weight = 1u128 << j;
+        weighted = cipher.plus(weighted, (p.power(bit.0, weight), p.power(bit.1, weight)));
+    }
+    cipher.minus(target, weighted)
+}
+
+/**
+ * old under old_key in the group of cipher and new under new_key in
+ * the group of next encode the same amount.
+ */
+pub fn migration_statement(
+    cipher:&EGICipher<u128>, old_key:u128, old:(u128,u128),
+    next:&EGICipher<u128>, new_key:u128, new:(u128,u128),
+    old_bits:&[(u128,u128)], new_bits:&[(u128,u128)],
+) -> MigrationStatement<u128> {
+    let mut pairs = Vec::new();
+    for (old_bit, new_bit) in old_bits.iter().zip(new_bits.iter()) {
+        let Or(old_zero, old_one) = cipher.bit_statement(old_key, *old_bit);
+        let Or(new_zero, new_one) = next.bit_statement(new_key, *new_bit);
+        pairs.push(Or(And(old_zero, new_zero), And(old_one, new_one)));
+    }
+    let old_rest = rest(cipher, old, old_bits);
+    let new_rest = rest(next, new, new_bits);
+    And(
+        All(pairs),
+        And(
+            DLogEq {prime:cipher.prime, bases:(cipher.gamma, old_rest.1), targets:(old_key, old_rest.0)},
+            DLogEq {prime:next.prime, bases:(next.gamma, new_key), targets:(new_rest.1, new_rest.0)},
+        ),
+    )
+}
+
+/* The new group is part of what the proof is bound to */
+pub fn migration_context(next:&EGICipher<u128>, context:&[u8]) -> Vec<u8> {
+    (context, next.gamma, next.prime).encode()
+}
+
+pub fn migrated(
+    cipher:&EGICipher<u128>, old_key:u128, old:(u128,u128),
+    next:&EGICipher<u128>, new_key:u128, new:(u128,u128),
+    width:usize, context:&[u8], proof:&MigrationProof<u128>,
+) -> bool {
+    cipher.range_fits(width) && next.range_fits(width)
+        && proof.old_bits.len() == width && proof.new_bits.len() == width
+        && sigma::verify(
+            cipher,
+            &migration_statement(cipher, old_key, old, next, new_key, new, &proof.old_bits, &proof.new_bits),
+            &migration_context(next, context),
+            &proof.proof,
+        )
+}
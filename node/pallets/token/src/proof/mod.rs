@@ -0,0 +1,190 @@
+use codec::{Encode, Decode};
+use crate::cipher::EGICipher;
+use crate::primering::{
+    PrimeRing,
+    subgroup_order,
+    in_subgroup,
+};
+use frame_support::{
+    dispatch::{Vec},
+};
+use sp_io::hashing::blake2_256;
+use sp_runtime::RuntimeDebug;
+
+use crate::cipher::CipherFunctor;
+
+pub mod sigma;
+pub mod envelope;
+pub mod disclosure;
+pub mod migration;
+pub mod tag;
+
+use self::sigma::{
+    SigmaProof,
+    DLogEq,
+    And,
+    Or,
+    All,
+    Equation,
+};
+
+/**
+ * Range proof of a cipher under key y: the amount is split into bits
+ * b_j, each encoded as a cipher C_j under y, and the sigma proof shows
+ *  - every C_j is encoded from either zero or one,
+ *  - target / Π_j C_j^(2^j) is encoded from zero.
+ * The number of bits is the width of the range, the verifier only
+ * accepts the width it is configured with.
+ */
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct RangeProof<T> {
+    pub bits: Vec<(T, T)>,
+    pub proof: SigmaProof<T>,
+}
+
+/* C = (C0, C1) encoded from zero or one under y, knowing its randomness */
+pub type BitStatement<T> = Or<DLogEq<T>, DLogEq<T>>;
+
+/*
+ * The remainder Q encodes zero, either the prover knows the randomness
+ * (Q = (y^r, γ^r)) or the secret key (y = γ^x and Q.0 = Q.1^x).
+ */
+pub type ZeroStatement<T> = Or<DLogEq<T>, DLogEq<T>>;
+
+pub type RangeStatement<T> = And<All<BitStatement<T>>, ZeroStatement<T>>;
+
+pub trait CipherProof <K, F, T> {
+    /* The cipher text t under key is encoded from either zero or one */
+    fn bit_statement(&self, key:K, t:T) -> BitStatement<F>;
+    fn bit_proof(&self, key:K, t:T, context:&[u8], proof:&SigmaProof<F>) -> bool;
+    /* The cipher text under key is encoded from x ∈ [0, 2^k) */
+    fn range_statement(&self, key:K, target:T, bits:&[T]) -> RangeStatement<F>;
+    /* Two amounts of the given width added up can not wrap around the group order q */
+    fn range_fits(&self, width:usize) -> bool;
+    fn within_exp(&self, key:K, target:T, width:usize, context:&[u8], proof:&RangeProof<F>) -> bool;
+    /* Equations of within_exp for batch verification, false if malformed */
+    fn range_equations(&self, key:K, target:T, width:usize, context:&[u8], proof:&RangeProof<F>, out:&mut Vec<Equation<F>>) -> bool;
+}
+
+impl CipherProof<u128, u128, (u128,u128)> for EGICipher<u128> {
+    fn bit_statement(&self, key:u128, t:(u128,u128)) -> BitStatement<u128> {
+        // cipher_text = γ^a * y^r, γ^r
+        /* To proof that a = 1 or 0 it is sufficient to proof
+         * the knowledge of r with (γ, y)^r = (t.1, t.0)
+         * or (γ, y)^r = (t.1, t.0 / γ).
+         */
+        let p = self.prime;
+        Or(
+            DLogEq {prime:p, bases:(self.gamma, key), targets:(t.1, t.0)},
+            DLogEq {prime:p, bases:(self.gamma, key), targets:(t.1, p.div(t.0, self.gamma))},
+        )
+    }
+
+    fn bit_proof(&self, key:u128, t:(u128,u128), context:&[u8], proof:&SigmaProof<u128>) -> bool {
+        sigma::verify(self, &self.bit_statement(key, t), context, proof)
+    }
+
+    /* Suppose that
+     * R equals to Σ_i x_i*2^i, then x_i needs to be either one or zero
+     * and ∏_i cipher(x_i)^(2^i) needs to encode the same as cipher(R).
+     */
+    fn range_statement(&self, key:u128, target:(u128,u128), bits:&[(u128,u128)]) -> RangeStatement<u128> {
+        let p = self.prime;
+        let mut weighted = (1, 1);
+        let mut bit_statements = Vec::new();
+        for (j, bit) in bits.iter().enumerate() {
+            let weight = 1u128 << j;
+            weighted = self.plus(weighted, (p.power(bit.0, weight), p.power(bit.1, weight)));
+            bit_statements.push(self.bit_statement(key, *bit));
+        }
+        let rest = self.minus(target, weighted);
+        And(
+            All(bit_statements),
+            Or(
+                DLogEq {prime:p, bases:(self.gamma, key), targets:(rest.1, rest.0)},
+                DLogEq {prime:p, bases:(self.gamma, rest.1), targets:(key, rest.0)},
+            ),
+        )
+    }
+
+    fn range_fits(&self, width:usize) -> bool {
+        width > 0 && width < 127 && (1u128 << (width + 1)) <= subgroup_order(self.prime)
+    }
+
+    fn within_exp(&self, key:u128, target:(u128,u128), width:usize, context:&[u8], proof:&RangeProof<u128>) -> bool {
+        self.range_fits(width) && proof.bits.len() == width
+            && sigma::verify(self, &self.range_statement(key, target, &proof.bits), context, &proof.proof)
+    }
+
+    fn range_equations(&self, key:u128, target:(u128,u128), width:usize, context:&[u8], proof:&RangeProof<u128>, out:&mut Vec<Equation<u128>>) -> bool {
+        self.range_fits(width) && proof.bits.len() == width
+            && sigma::equations(self, &self.range_statement(key, target, &proof.bits), context, &proof.proof, out)
+    }
+}
+
+/**
+ * Non-interactive proof that two ciphertexts, encoded under the keys
+ * y1 and y2, hide the same amount b. The prover knows b and the
+ * randomness r1, r2 of both ciphertexts.
+ */
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct EqualityProof<T> {
+    /* (γ^kb * y1^k1, γ^k1) and (γ^kb * y2^k2, γ^k2) */
+    pub commit_src: (T, T),
+    pub commit_dest: (T, T),
+    /* kb + c*b, k1 + c*r1 and k2 + c*r2 modulo the group order q */
+    pub amount: T,
+    pub rand_src: T,
+    pub rand_dest: T,
+}
+
+pub trait CipherEquality<T> {
+    /* Fiat-Shamir challenge of a transcript, reduced to an exponent */
+    fn challenge(&self, items:&[(T,T)], context:&[u8]) -> T;
+    /* Prove that src under src_key and dest under dest_key hide the same amount */
+    fn equal_amount(&self, src_key:T, src:(T,T), dest_key:T, dest:(T,T), context:&[u8], proof:&EqualityProof<T>) -> bool;
+}
+
+/*
+ * Fiat-Shamir: hash the group and a transcript into an exponent,
+ * exponents live modulo the prime order q = (p - 1) / 2 of the group.
+ * Two distinct challenges differ by an invertible exponent, which is
+ * what lets a sigma protocol extract its witness.
+ */
+pub fn fiat_shamir<E:Encode>(cipher:&EGICipher<u128>, transcript:&E) -> u128 {
+    let hash = blake2_256(&(cipher.gamma, cipher.prime, transcript).encode());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    u128::from_le_bytes(bytes) % subgroup_order(cipher.prime)
+}
+
+impl CipherEquality<u128> for EGICipher<u128> {
+    fn challenge(&self, items:&[(u128,u128)], context:&[u8]) -> u128 {
+        fiat_shamir(self, &(context, items))
+    }
+
+    /*
+     * With challenge c, the responses satisfy
+     * γ^sb * y^sr = commit.0 * t.0^c and γ^sr = commit.1 * t.1^c
+     * on both sides, and sb is shared so both hide the same b. Every
+     * element has to lie in the group of order q, see sigma.rs.
+     */
+    fn equal_amount(&self, src_key:u128, src:(u128,u128), dest_key:u128, dest:(u128,u128), context:&[u8], proof:&EqualityProof<u128>) -> bool {
+        let p = self.prime;
+        let elements = [src_key, dest_key, src.0, src.1, dest.0, dest.1,
+            proof.commit_src.0, proof.commit_src.1, proof.commit_dest.0, proof.commit_dest.1];
+        if !elements.iter().all(|x| in_subgroup(p, *x)) {
+            return false;
+        }
+        let c = self.challenge(&[
+            (src_key, dest_key), src, dest, proof.commit_src, proof.commit_dest
+        ], context);
+        let opens = |key:u128, rand:u128, commit:(u128,u128), t:(u128,u128)| {
+            p.mul(p.power(self.gamma, proof.amount), p.power(key, rand))
+                == p.mul(commit.0, p.power(t.0, c))
+            && p.power(self.gamma, rand) == p.mul(commit.1, p.power(t.1, c))
+        };
+        opens(src_key, proof.rand_src, proof.commit_src, src)
+            && opens(dest_key, proof.rand_dest, proof.commit_dest, dest)
+    }
+}
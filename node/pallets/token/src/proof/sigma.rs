@@ -0,0 +1,594 @@
+use codec::{Encode, Decode};
+use frame_support::{
+    dispatch::{Vec},
+};
+use sp_runtime::RuntimeDebug;
+
+use crate::cipher::EGICipher;
+use crate::primering::{
+    PrimeRing,
+    subgroup_order,
+    in_subgroup,
+};
+use crate::proof::fiat_shamir;
+
+//
+// Sigma protocols over the group of EGICipher.
+//
+// The group is the subgroup of prime order q of Z_p*, p = 2q + 1 a safe
+// prime. Challenges and responses are exponents modulo q, and since q
+// is prime two accepting transcripts with distinct challenges reveal
+// the witness. The verifier checks every element of the statement and
+// every commitment to lie in the group: outside of it an element of
+// order 2 could be multiplied in and cancel for half the challenges.
+//
+// A sigma protocol is a three move protocol, the prover sends
+// commitments, receives a challenge c and answers with responses.
+// A statement knows how to run both halves, and the combinators
+// And, Or and All build bigger statements out of smaller ones:
+//
+//  - And(A, B) proves both under the same challenge,
+//  - Or(A, B) proves one of them, the other one is simulated with
+//    a challenge of the prover's choice (Cramer, Damgård and
+//    Schoenmakers), the verifier only checks that both challenges
+//    sum up to c,
+//  - All(S_0, ..., S_k) is And over a list of the same statement.
+//
+// Transcripts are flat vectors, each statement tells how many
+// commitments and responses it takes so combinators can split them.
+// The prover keeps one nonce per response, for Or the nonces also
+// hold the simulated challenge and responses.
+//
+// Fiat-Shamir turns it into a non-interactive proof by hashing the
+// public statement and the commitments into c, together with a context
+// (chain, account, nonce) the proof is bound to so it can not be
+// replayed anywhere else.
+//
+// Every check of the verifier is an equation Π_j base_j^exp_j == 1.
+// Instead of checking them one by one, equations of many proofs can
+// be batched: raise each to a random weight and multiply them all,
+// the powers of a shared base (γ, a key) are computed only once.
+//
+
+/* Π_j base_j^exp_j == 1, as (base_j, exp_j) */
+pub type Equation<T> = Vec<(T, T)>;
+
+pub trait Sigma<T> {
+    type Witness;
+
+    /* The modulus of the group the statement lives in */
+    fn prime(&self) -> T;
+    fn commit_len(&self) -> usize;
+    fn response_len(&self) -> usize;
+
+    /* The public inputs, they go into the Fiat-Shamir transcript */
+    fn statement(&self, out:&mut Vec<T>);
+
+    /* Prover: first move, push commitments and keep the nonces */
+    fn commit<R:FnMut() -> T>(&self, w:&Self::Witness, rng:&mut R, commits:&mut Vec<T>, nonces:&mut Vec<T>);
+    /* Prover: last move, answer the challenge c */
+    fn respond(&self, w:&Self::Witness, nonces:&[T], c:T, responses:&mut Vec<T>);
+    /* Prover: produce an accepting transcript for c without a witness */
+    fn simulate<R:FnMut() -> T>(&self, c:T, rng:&mut R, commits:&mut Vec<T>, responses:&mut Vec<T>);
+
+    /* Verifier */
+    fn verify(&self, commits:&[T], c:T, responses:&[T]) -> bool;
+    /* Verifier: the equations verify checks, for batching */
+    fn equations(&self, commits:&[T], c:T, responses:&[T], out:&mut Vec<Equation<T>>);
+}
+
+/* Every element lies in the group of prime order of Z_prime* */
+fn in_group(prime:u128, elements:&[u128]) -> bool {
+    elements.iter().all(|x| in_subgroup(prime, *x))
+}
+
+/* base^s == commit * target^c */
+fn schnorr_equation(prime:u128, base:u128, target:u128, commit:u128, c:u128, s:u128) -> Equation<u128> {
+    let q = subgroup_order(prime);
+    let mut eq = Vec::new();
+    eq.push((base, s));
+    eq.push((commit, q - 1));
+    eq.push((target, q.minus(0, c)));
+    eq
+}
+
+/**
+ * Non-interactive proof produced by Fiat-Shamir.
+ */
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct SigmaProof<T> {
+    pub commitments: Vec<T>,
+    pub responses: Vec<T>,
+}
+
+/**
+ * Knowledge of w with base^w = target.
+ */
+pub struct DLog<T> {
+    pub prime: T,
+    pub base: T,
+    pub target: T,
+}
+
+/**
+ * Knowledge of w with bases.0^w = targets.0 and bases.1^w = targets.1,
+ * e.g. a cipher (C0, C1) encodes zero under y: (γ, y)^r = (C1, C0).
+ */
+pub struct DLogEq<T> {
+    pub prime: T,
+    pub bases: (T, T),
+    pub targets: (T, T),
+}
+
+pub struct And<A, B>(pub A, pub B);
+
+pub struct Or<A, B>(pub A, pub B);
+
+pub struct All<S>(pub Vec<S>);
+
+/* Witness for Or: which side the prover knows */
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl Sigma<u128> for DLog<u128> {
+    type Witness = u128;
+
+    fn prime(&self) -> u128 { self.prime }
+    fn commit_len(&self) -> usize { 1 }
+    fn response_len(&self) -> usize { 1 }
+
+    fn statement(&self, out:&mut Vec<u128>) {
+        out.push(self.base);
+        out.push(self.target);
+    }
+
+    fn commit<R:FnMut() -> u128>(&self, _w:&u128, rng:&mut R, commits:&mut Vec<u128>, nonces:&mut Vec<u128>) {
+        let k = rng();
+        commits.push(self.prime.power(self.base, k));
+        nonces.push(k);
+    }
+
+    fn respond(&self, w:&u128, nonces:&[u128], c:u128, responses:&mut Vec<u128>) {
+        let q = subgroup_order(self.prime);
+        responses.push(q.plus(nonces[0], q.mul(c, *w)));
+    }
+
+    fn simulate<R:FnMut() -> u128>(&self, c:u128, rng:&mut R, commits:&mut Vec<u128>, responses:&mut Vec<u128>) {
+        let p = self.prime;
+        let s = rng();
+        commits.push(p.div(p.power(self.base, s), p.power(self.target, c)));
+        responses.push(s);
+    }
+
+    fn verify(&self, commits:&[u128], c:u128, responses:&[u128]) -> bool {
+        let p = self.prime;
+        in_group(p, &[self.base, self.target, commits[0]])
+            && p.power(self.base, responses[0]) == p.mul(commits[0], p.power(self.target, c))
+    }
+
+    fn equations(&self, commits:&[u128], c:u128, responses:&[u128], out:&mut Vec<Equation<u128>>) {
+        out.push(schnorr_equation(self.prime, self.base, self.target, commits[0], c, responses[0]));
+    }
+}
+
+impl Sigma<u128> for DLogEq<u128> {
+    type Witness = u128;
+
+    fn prime(&self) -> u128 { self.prime }
+    fn commit_len(&self) -> usize { 2 }
+    fn response_len(&self) -> usize { 1 }
+
+    fn statement(&self, out:&mut Vec<u128>) {
+        out.push(self.bases.0);
+        out.push(self.bases.1);
+        out.push(self.targets.0);
+        out.push(self.targets.1);
+    }
+
+    fn commit<R:FnMut() -> u128>(&self, _w:&u128, rng:&mut R, commits:&mut Vec<u128>, nonces:&mut Vec<u128>) {
+        let p = self.prime;
+        let k = rng();
+        commits.push(p.power(self.bases.0, k));
+        commits.push(p.power(self.bases.1, k));
+        nonces.push(k);
+    }
+
+    fn respond(&self, w:&u128, nonces:&[u128], c:u128, responses:&mut Vec<u128>) {
+        let q = subgroup_order(self.prime);
+        responses.push(q.plus(nonces[0], q.mul(c, *w)));
+    }
+
+    fn simulate<R:FnMut() -> u128>(&self, c:u128, rng:&mut R, commits:&mut Vec<u128>, responses:&mut Vec<u128>) {
+        let p = self.prime;
+        let s = rng();
+        commits.push(p.div(p.power(self.bases.0, s), p.power(self.targets.0, c)));
+        commits.push(p.div(p.power(self.bases.1, s), p.power(self.targets.1, c)));
+        responses.push(s);
+    }
+
+    fn verify(&self, commits:&[u128], c:u128, responses:&[u128]) -> bool {
+        let p = self.prime;
+        in_group(p, &[self.bases.0, self.bases.1, self.targets.0, self.targets.1, commits[0], commits[1]])
+            && p.power(self.bases.0, responses[0]) == p.mul(commits[0], p.power(self.targets.0, c))
+            && p.power(self.bases.1, responses[0]) == p.mul(commits[1], p.power(self.targets.1, c))
+    }
+
+    fn equations(&self, commits:&[u128], c:u128, responses:&[u128], out:&mut Vec<Equation<u128>>) {
+        out.push(schnorr_equation(self.prime, self.bases.0, self.targets.0, commits[0], c, responses[0]));
+        out.push(schnorr_equation(self.prime, self.bases.1, self.targets.1, commits[1], c, responses[0]));
+    }
+}
+
+impl<A:Sigma<u128>, B:Sigma<u128>> Sigma<u128> for And<A, B> {
+    type Witness = (A::Witness, B::Witness);
+
+    fn prime(&self) -> u128 { self.0.prime() }
+    fn commit_len(&self) -> usize { self.0.commit_len() + self.1.commit_len() }
+    fn response_len(&self) -> usize { self.0.response_len() + self.1.response_len() }
+
+    fn statement(&self, out:&mut Vec<u128>) {
+        self.0.statement(out);
+        self.1.statement(out);
+    }
+
+    fn commit<R:FnMut() -> u128>(&self, w:&Self::Witness, rng:&mut R, commits:&mut Vec<u128>, nonces:&mut Vec<u128>) {
+        self.0.commit(&w.0, rng, commits, nonces);
+        self.1.commit(&w.1, rng, commits, nonces);
+    }
+
+    fn respond(&self, w:&Self::Witness, nonces:&[u128], c:u128, responses:&mut Vec<u128>) {
+        let (left, right) = nonces.split_at(self.0.response_len());
+        self.0.respond(&w.0, left, c, responses);
+        self.1.respond(&w.1, right, c, responses);
+    }
+
+    fn simulate<R:FnMut() -> u128>(&self, c:u128, rng:&mut R, commits:&mut Vec<u128>, responses:&mut Vec<u128>) {
+        self.0.simulate(c, rng, commits, responses);
+        self.1.simulate(c, rng, commits, responses);
+    }
+
+    fn verify(&self, commits:&[u128], c:u128, responses:&[u128]) -> bool {
+        let (left_c, right_c) = commits.split_at(self.0.commit_len());
+        let (left_r, right_r) = responses.split_at(self.0.response_len());
+        self.0.verify(left_c, c, left_r) && self.1.verify(right_c, c, right_r)
+    }
+
+    fn equations(&self, commits:&[u128], c:u128, responses:&[u128], out:&mut Vec<Equation<u128>>) {
+        let (left_c, right_c) = commits.split_at(self.0.commit_len());
+        let (left_r, right_r) = responses.split_at(self.0.response_len());
+        self.0.equations(left_c, c, left_r, out);
+        self.1.equations(right_c, c, right_r, out);
+    }
+}
+
+/*
+ * Responses of Or are [c_left] ++ left ++ right, the challenge of the
+ * right side is c - c_left. The nonces follow the same layout with the
+ * simulated challenge first and the simulated responses in place.
+ */
+impl<A:Sigma<u128>, B:Sigma<u128>> Sigma<u128> for Or<A, B> {
+    type Witness = Either<A::Witness, B::Witness>;
+
+    fn prime(&self) -> u128 { self.0.prime() }
+    fn commit_len(&self) -> usize { self.0.commit_len() + self.1.commit_len() }
+    fn response_len(&self) -> usize { 1 + self.0.response_len() + self.1.response_len() }
+
+    fn statement(&self, out:&mut Vec<u128>) {
+        self.0.statement(out);
+        self.1.statement(out);
+    }
+
+    fn commit<R:FnMut() -> u128>(&self, w:&Self::Witness, rng:&mut R, commits:&mut Vec<u128>, nonces:&mut Vec<u128>) {
+        /* reduced, a statement may span groups of other orders */
+        let c_sim = rng() % subgroup_order(self.prime());
+        nonces.push(c_sim);
+        match w {
+            Either::Left(w) => {
+                self.0.commit(w, rng, commits, nonces);
+                self.1.simulate(c_sim, rng, commits, nonces);
+            },
+            Either::Right(w) => {
+                self.0.simulate(c_sim, rng, commits, nonces);
+                self.1.commit(w, rng, commits, nonces);
+            },
+        }
+    }
+
+    fn respond(&self, w:&Self::Witness, nonces:&[u128], c:u128, responses:&mut Vec<u128>) {
+        let q = subgroup_order(self.prime());
+        let c_sim = nonces[0];
+        let (left, right) = nonces[1..].split_at(self.0.response_len());
+        match w {
+            Either::Left(w) => {
+                let c_left = q.minus(c, c_sim);
+                responses.push(c_left);
+                self.0.respond(w, left, c_left, responses);
+                responses.extend_from_slice(right);
+            },
+            Either::Right(w) => {
+                responses.push(c_sim);
+                responses.extend_from_slice(left);
+                self.1.respond(w, right, q.minus(c, c_sim), responses);
+            },
+        }
+    }
+
+    fn simulate<R:FnMut() -> u128>(&self, c:u128, rng:&mut R, commits:&mut Vec<u128>, responses:&mut Vec<u128>) {
+        let q = subgroup_order(self.prime());
+        let c_left = rng() % q;
+        responses.push(c_left);
+        self.0.simulate(c_left, rng, commits, responses);
+        self.1.simulate(q.minus(c, c_left), rng, commits, responses);
+    }
+
+    fn verify(&self, commits:&[u128], c:u128, responses:&[u128]) -> bool {
+        let q = subgroup_order(self.prime());
+        let c_left = responses[0] % q;
+        let (left_c, right_c) = commits.split_at(self.0.commit_len());
+        let (left_r, right_r) = responses[1..].split_at(self.0.response_len());
+        self.0.verify(left_c, c_left, left_r) && self.1.verify(right_c, q.minus(c, c_left), right_r)
+    }
+
+    /* The split of the challenge is enforced by construction, only the group equations remain */
+    fn equations(&self, commits:&[u128], c:u128, responses:&[u128], out:&mut Vec<Equation<u128>>) {
+        let q = subgroup_order(self.prime());
+        let c_left = responses[0] % q;
+        let (left_c, right_c) = commits.split_at(self.0.commit_len());
+        let (left_r, right_r) = responses[1..].split_at(self.0.response_len());
+        self.0.equations(left_c, c_left, left_r, out);
+        self.1.equations(right_c, q.minus(c, c_left), right_r, out);
+    }
+}
+
+impl<S:Sigma<u128>> Sigma<u128> for All<S> {
+    type Witness = Vec<S::Witness>;
+
+    /* All is never empty, it would prove nothing */
+    fn prime(&self) -> u128 { self.0[0].prime() }
+    fn commit_len(&self) -> usize { self.0.iter().map(|s| s.commit_len()).sum() }
+    fn response_len(&self) -> usize { self.0.iter().map(|s| s.response_len()).sum() }
+
+    fn statement(&self, out:&mut Vec<u128>) {
+        for s in self.0.iter() {
+            s.statement(out);
+        }
+    }
+
+    fn commit<R:FnMut() -> u128>(&self, w:&Self::Witness, rng:&mut R, commits:&mut Vec<u128>, nonces:&mut Vec<u128>) {
+        for (s, w) in self.0.iter().zip(w.iter()) {
+            s.commit(w, rng, commits, nonces);
+        }
+    }
+
+    fn respond(&self, w:&Self::Witness, nonces:&[u128], c:u128, responses:&mut Vec<u128>) {
+        let mut rest = nonces;
+        for (s, w) in self.0.iter().zip(w.iter()) {
+            let (head, tail) = rest.split_at(s.response_len());
+            s.respond(w, head, c, responses);
+            rest = tail;
+        }
+    }
+
+    fn simulate<R:FnMut() -> u128>(&self, c:u128, rng:&mut R, commits:&mut Vec<u128>, responses:&mut Vec<u128>) {
+        for s in self.0.iter() {
+            s.simulate(c, rng, commits, responses);
+        }
+    }
+
+    fn verify(&self, commits:&[u128], c:u128, responses:&[u128]) -> bool {
+        let mut rest_c = commits;
+        let mut rest_r = responses;
+        for s in self.0.iter() {
+            let (head_c, tail_c) = rest_c.split_at(s.commit_len());
+            let (head_r, tail_r) = rest_r.split_at(s.response_len());
+            if !s.verify(head_c, c, head_r) {
+                return false;
+            }
+            rest_c = tail_c;
+            rest_r = tail_r;
+        }
+        true
+    }
+
+    fn equations(&self, commits:&[u128], c:u128, responses:&[u128], out:&mut Vec<Equation<u128>>) {
+        let mut rest_c = commits;
+        let mut rest_r = responses;
+        for s in self.0.iter() {
+            let (head_c, tail_c) = rest_c.split_at(s.commit_len());
+            let (head_r, tail_r) = rest_r.split_at(s.response_len());
+            s.equations(head_c, c, head_r, out);
+            rest_c = tail_c;
+            rest_r = tail_r;
+        }
+    }
+}
+
+/**
+ * Fiat-Shamir: derive the challenge from the statement and commitments.
+ */
+pub fn prove<S:Sigma<u128>, R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, s:&S, w:&S::Witness, context:&[u8], rng:&mut R
+) -> SigmaProof<u128> {
+    let mut commitments = Vec::new();
+    let mut nonces = Vec::new();
+    s.commit(w, rng, &mut commitments, &mut nonces);
+    let c = challenge(cipher, s, context, &commitments);
+    let mut responses = Vec::new();
+    s.respond(w, &nonces, c, &mut responses);
+    SigmaProof { commitments, responses }
+}
+
+pub fn verify<S:Sigma<u128>>(cipher:&EGICipher<u128>, s:&S, context:&[u8], proof:&SigmaProof<u128>) -> bool {
+    proof.commitments.len() == s.commit_len()
+        && proof.responses.len() == s.response_len()
+        && s.verify(&proof.commitments, challenge(cipher, s, context, &proof.commitments), &proof.responses)
+}
+
+/**
+ * Collect the equations of a proof instead of checking them,
+ * false if the proof does not even have the right shape.
+ */
+pub fn equations<S:Sigma<u128>>(
+    cipher:&EGICipher<u128>, s:&S, context:&[u8], proof:&SigmaProof<u128>, out:&mut Vec<Equation<u128>>
+) -> bool {
+    if proof.commitments.len() != s.commit_len() || proof.responses.len() != s.response_len() {
+        return false;
+    }
+    s.equations(&proof.commitments, challenge(cipher, s, context, &proof.commitments), &proof.responses, out);
+    true
+}
+
+/**
+ * Check Π_k (Π_j base_kj^exp_kj)^ρ_k == 1 with weights ρ_k derived from
+ * all the equations, so none of them can be chosen to cancel out another.
+ *
+ * This only holds within the group of prime order q, every base is
+ * checked to lie in it. There a false equation has a value of order q,
+ * whatever the others are exactly one weight modulo q cancels it, and
+ * with ρ_k drawn from 1..q the batch accepts it with chance 1/(q - 1).
+ * Outside of it an element of order 2 would cancel for every even ρ_k.
+ */
+pub fn batch_verify(cipher:&EGICipher<u128>, equations:&[Equation<u128>]) -> bool {
+    let p = cipher.prime;
+    let q = subgroup_order(p);
+    let seed = fiat_shamir(cipher, &equations);
+    let mut terms = Vec::new();
+    for (k, eq) in equations.iter().enumerate() {
+        let rho = fiat_shamir(cipher, &(seed, k as u64)) % (q - 1) + 1;
+        for (base, exp) in eq.iter() {
+            terms.push((*base, q.mul(rho, *exp)));
+        }
+    }
+    /* one exponentiation per distinct base */
+    terms.sort_unstable_by_key(|t| t.0);
+    let mut acc = 1;
+    let mut i = 0;
+    while i < terms.len() {
+        let base = terms[i].0;
+        if !in_subgroup(p, base) {
+            return false;
+        }
+        let mut exp = 0;
+        while i < terms.len() && terms[i].0 == base {
+            exp = q.plus(exp, terms[i].1);
+            i += 1;
+        }
+        acc = p.mul(acc, p.power(base, exp));
+    }
+    acc == 1
+}
+
+fn challenge<S:Sigma<u128>>(cipher:&EGICipher<u128>, s:&S, context:&[u8], commitments:&[u128]) -> u128 {
+    let mut transcript = Vec::new();
+    s.statement(&mut transcript);
+    fiat_shamir(cipher, &(context, transcript, commitments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* a safe prime, 7 has order q = (P - 1) / 2 */
+    const P:u128 = 2147483579;
+
+    fn cipher() -> EGICipher<u128> {
+        EGICipher { gamma: 7, prime: P }
+    }
+
+    fn rng() -> impl FnMut() -> u128 {
+        let mut seed = 12345u128;
+        move || { seed = P.mul(seed, 48271); seed }
+    }
+
+    #[test]
+    fn sigma_and_or() {
+        let c = cipher();
+        let (x, w) = (1234567, 7654321);
+        let known = DLog { prime: P, base: 7, target: P.power(7, x) };
+        let other = DLog { prime: P, base: 7, target: P.power(7, w) };
+        let unknown = DLog { prime: P, base: 7, target: 9 };
+        let mut rng = rng();
+
+        let s = And(known, other);
+        let proof = prove(&c, &s, &(x, w), b"alice", &mut rng);
+        assert!(verify(&c, &s, b"alice", &proof));
+        assert!(!verify(&c, &s, b"bob", &proof));
+        assert!(!verify(&c, &And(s.0, unknown), b"alice", &proof));
+
+        let s = Or(DLog { prime: P, base: 7, target: 9 }, DLog { prime: P, base: 7, target: P.power(7, x) });
+        let proof = prove(&c, &s, &Either::Right(x), &[], &mut rng);
+        assert!(verify(&c, &s, &[], &proof));
+        let mut bad = proof.clone();
+        bad.responses[0] += 1;
+        assert!(!verify(&c, &s, &[], &bad));
+
+        let mut eqs = Vec::new();
+        assert!(equations(&c, &s, &[], &proof, &mut eqs));
+        assert!(batch_verify(&c, &eqs));
+        assert!(equations(&c, &s, &[], &bad, &mut eqs));
+        assert!(!batch_verify(&c, &eqs));
+    }
+
+    #[test]
+    fn batch_stays_in_the_group() {
+        let c = cipher();
+        let mut rng = rng();
+        let x = 1234567;
+        let s = DLog { prime: P, base: 7, target: P.power(7, x) };
+        let mut eqs = Vec::new();
+        for _ in 0..4 {
+            let proof = prove(&c, &s, &x, &[], &mut rng);
+            assert!(equations(&c, &s, &[], &proof, &mut eqs));
+        }
+        assert!(batch_verify(&c, &eqs));
+        /* one commitment negated, or two so that the signs cancel, -1 is outside the group */
+        let negate = |eq:&mut Equation<u128>| eq.push((P - 1, 1));
+        let mut one = eqs.clone();
+        negate(&mut one[0]);
+        assert!(!batch_verify(&c, &one));
+        let mut two = one.clone();
+        negate(&mut two[1]);
+        assert!(!batch_verify(&c, &two));
+
+        /* x proves -y = -7^x for an even challenge, all such proofs stay out of the batch */
+        let negated = DLog { prime: P, base: 7, target: P - P.power(7, x) };
+        let mut forged = 0;
+        for context in 0u8..16 {
+            let k = rng();
+            let commitments = vec![P.power(7, k)];
+            let ch = challenge(&c, &negated, &[context], &commitments);
+            if ch % 2 == 0 {
+                let proof = SigmaProof { commitments, responses: vec![(k + ch * x) % ((P - 1) / 2)] };
+                /* the equation itself holds */
+                assert_eq!(P.power(7, proof.responses[0]), P.mul(proof.commitments[0], P.power(negated.target, ch)));
+                let mut eqs = Vec::new();
+                assert!(equations(&c, &negated, &[context], &proof, &mut eqs));
+                assert!(!batch_verify(&c, &eqs));
+                forged += 1;
+            }
+        }
+        assert!(forged > 0);
+    }
+
+    #[test]
+    fn elements_outside_the_group_fail() {
+        let c = cipher();
+        let mut rng = rng();
+        let x = 1234567;
+        let y = P.power(7, x);
+        let s = DLog { prime: P, base: 7, target: y };
+        /* -y differs from y by an element of order 2, a proof for y holds for -y whenever c is even */
+        let negated = DLog { prime: P, base: 7, target: P - y };
+        for context in 0u8..8 {
+            let proof = prove(&c, &s, &x, &[context], &mut rng);
+            assert!(verify(&c, &s, &[context], &proof));
+            assert!(!verify(&c, &negated, &[context], &proof));
+        }
+        /* and so does a commitment outside it */
+        let mut proof = prove(&c, &s, &x, &[], &mut rng);
+        proof.commitments[0] = P - proof.commitments[0];
+        assert!(!verify(&c, &s, &[], &proof));
+    }
+}
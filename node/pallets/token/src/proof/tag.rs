@@ -0,0 +1,94 @@
+use codec::{Encode, Decode};
+use frame_support::{
+    dispatch::{Vec},
+};
+use sp_io::hashing::blake2_256;
+use sp_runtime::RuntimeDebug;
+
+use crate::cipher::EGICipher;
+use crate::primering::{
+    PrimeRing,
+    in_subgroup,
+};
+use crate::proof::{
+    CipherProof,
+    RangeProof,
+};
+use crate::proof::sigma::{
+    self,
+    SigmaProof,
+    DLogEq,
+    Equation,
+};
+
+//
+// Epoch tags, as in Zether.
+//
+// An anonymous transfer debits every ring member through pending, so
+// that the current balance other proofs are built against stays as it
+// is until the next roll over. Without more, the sender could then
+// spend its current once in the ring and once more in its own name.
+//
+// Each epoch e has a generator g_e nobody knows a logarithm of, and the
+// tag of an account with secret key x is u = g_e^x. The ring proof shows
+// the tag of its sender without telling which member it belongs to, and
+// every other proof spending out of current shows the tag of its key.
+// A tag is spent once the ring showed it: its owner can neither send
+// from the ring again nor spend out of current until the epoch ends.
+//
+// g_e and the tags lie in the group of prime order q. A tag outside of
+// it, such as -u, would pass the proof for every even challenge while
+// counting as a tag of its own, and spend the same current twice.
+//
+
+/**
+ * Range proof of what remains of current after a debit, with the epoch
+ * tag of the key and the proof that both share the same secret key.
+ */
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct TaggedRange<T> {
+    pub range: RangeProof<T>,
+    pub tag: T,
+    pub proof: SigmaProof<T>,
+}
+
+/* The generator g_e hashed out of seed, a square other than 1 so of order q */
+pub fn epoch_generator(cipher:&EGICipher<u128>, seed:&[u8]) -> u128 {
+    let p = cipher.prime;
+    let mut counter = 0u32;
+    loop {
+        let hash = blake2_256(&(cipher.gamma, p, seed, counter).encode());
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&hash[..16]);
+        let x = u128::from_le_bytes(bytes) % p;
+        let g = p.mul(x, x);
+        if g > 1 {
+            return g;
+        }
+        counter += 1;
+    }
+}
+
+/* (γ, g_e)^x = (y, u) */
+pub fn tag_statement(cipher:&EGICipher<u128>, base:u128, key:u128, tag:u128) -> DLogEq<u128> {
+    DLogEq {prime:cipher.prime, bases:(cipher.gamma, base), targets:(key, tag)}
+}
+
+/* target under key is within range and tag is the one of key under base */
+pub fn tagged_range(
+    cipher:&EGICipher<u128>, base:u128, key:u128, target:(u128,u128), width:usize, context:&[u8], proof:&TaggedRange<u128>
+) -> bool {
+    in_subgroup(cipher.prime, proof.tag)
+        && cipher.within_exp(key, target, width, context, &proof.range)
+        && sigma::verify(cipher, &tag_statement(cipher, base, key, proof.tag), context, &proof.proof)
+}
+
+/* Equations of tagged_range for batch verification, false if malformed */
+pub fn tagged_equations(
+    cipher:&EGICipher<u128>, base:u128, key:u128, target:(u128,u128), width:usize, context:&[u8],
+    proof:&TaggedRange<u128>, out:&mut Vec<Equation<u128>>
+) -> bool {
+    in_subgroup(cipher.prime, proof.tag)
+        && cipher.range_equations(key, target, width, context, &proof.range, out)
+        && sigma::equations(cipher, &tag_statement(cipher, base, key, proof.tag), context, &proof.proof, out)
+}
@@ -0,0 +1,717 @@
+use frame_support::{
+    dispatch::{Vec},
+};
+
+use crate::balance::CipherText;
+use crate::cipher::{
+    EGICipher,
+    CipherFunctor,
+};
+use crate::primering::{
+    PrimeRing,
+    subgroup_order,
+};
+use crate::proof::{
+    CipherProof,
+    CipherEquality,
+    EqualityProof,
+    RangeProof,
+    fiat_shamir,
+};
+use crate::proof::disclosure::{
+    CipherDisclosure,
+    Disclosure,
+};
+use crate::proof::migration::{
+    MigrationProof,
+    migration_statement,
+    migration_context,
+};
+use crate::proof::tag::{
+    TaggedRange,
+    tag_statement,
+};
+use crate::proof::envelope::{
+    ProofEnvelope,
+    params_hash,
+};
+use crate::proof::sigma::{
+    self,
+    SigmaProof,
+    Either,
+};
+use crate::ring::{
+    RingProof,
+    AnonymousTransfer,
+    AnonymousProof,
+    BitCommitment,
+    MemberProof,
+};
+
+//
+// Client side prover, builds every proof the pallet verifies.
+//
+// The caller owns the secret key x of its account (y = γ^x) and knows
+// the amounts behind its own ciphers. Randomness comes from rng, which
+// must be a cryptographic source in anything but tests: a repeated or
+// guessable nonce reveals the witness. context is what the pallet binds
+// the transcript to, it has to match Module::proof_context of the
+// account submitting the proof.
+//
+
+/**
+ * How the prover opens a cipher encoded from zero:
+ * with the randomness r of (y^r, γ^r) or with the secret key x.
+ */
+#[derive(Clone, Copy)]
+pub enum Opening {
+    Randomness(u128),
+    SecretKey(u128),
+}
+
+/**
+ * A confidential debit: delta is the amount encoded under the owner's
+ * key, proof shows it is within range and remain_proof shows the same
+ * for the balance left afterwards.
+ */
+pub struct Debit {
+    pub delta: (u128, u128),
+    pub rand: u128,
+    pub proof: RangeProof<u128>,
+    pub remain_proof: RangeProof<u128>,
+}
+
+/**
+ * The public key of secret and value encrypted under it with randomness
+ * rand, as a genesis account lists them.
+ */
+pub fn genesis_account(cipher:&EGICipher<u128>, secret:u128, value:u128, rand:u128) -> (u128, (u128, u128)) {
+    let pubkey = cipher.prime.power(cipher.gamma, secret);
+    (pubkey, cipher.encode(pubkey, value, rand))
+}
+
+/**
+ * Seal a proof of scheme for the pallet's parameters, h being
+ * the pallet's second generator and nonce the one of context.
+ */
+pub fn seal<P:codec::Encode>(cipher:&EGICipher<u128>, h:u128, scheme:u16, nonce:u64, proof:&P) -> ProofEnvelope {
+    ProofEnvelope::seal(scheme, params_hash(cipher.gamma, cipher.prime, h), nonce, proof)
+}
+
+/**
+ * The cipher t under key is encoded from bit with randomness r.
+ */
+pub fn bit_proof<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, key:u128, bit:u128, r:u128, context:&[u8], rng:&mut R
+) -> SigmaProof<u128> {
+    let t = cipher.encode(key, bit, r);
+    let w = if bit == 0 { Either::Left(r) } else { Either::Right(r) };
+    sigma::prove(cipher, &cipher.bit_statement(key, t), &w, context, rng)
+}
+
+/**
+ * Range proof of target under key, which encodes amount < 2^width.
+ */
+pub fn range_proof<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, key:u128, target:(u128,u128), amount:u128, width:usize, opening:Opening, context:&[u8], rng:&mut R
+) -> RangeProof<u128> {
+    let q = subgroup_order(cipher.prime);
+    let mut bits = Vec::new();
+    let mut witness = Vec::new();
+    let mut weighted = 0;
+    for j in 0..width {
+        let bit = (amount >> j) & 1;
+        let r = rng() % q;
+        bits.push(cipher.encode(key, bit, r));
+        witness.push(if bit == 0 { Either::Left(r) } else { Either::Right(r) });
+        weighted = q.plus(weighted, q.mul(r, 1 << j));
+    }
+    /* target / Π_j bits_j^(2^j) is encoded from zero */
+    let rest = match opening {
+        Opening::Randomness(r) => Either::Left(q.minus(r, weighted)),
+        Opening::SecretKey(x) => Either::Right(x),
+    };
+    let statement = cipher.range_statement(key, target, &bits);
+    let proof = sigma::prove(cipher, &statement, &(witness, rest), context, rng);
+    RangeProof { bits, proof }
+}
+
+/**
+ * Proof for lock_balance: what remains after locking amount is within range.
+ */
+pub fn lock_balance<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, secret:u128, balance:&CipherText<u128>, value:u128, amount:u128, width:usize, context:&[u8], rng:&mut R
+) -> RangeProof<u128> {
+    let q = subgroup_order(cipher.prime);
+    let delta = cipher.encode(balance.pubkey, amount, balance.rel);
+    let remain = cipher.minus(balance.current, delta);
+    range_proof(cipher, balance.pubkey, remain, q.minus(value, amount), width, Opening::SecretKey(secret), context, rng)
+}
+
+/**
+ * Proof for unshield: what remains after taking the public amount out
+ * of current, encoded with randomness zero, is within range. burn and
+ * ChargeConfidentialFee take a public amount the same way.
+ */
+pub fn unshield<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, secret:u128, balance:&CipherText<u128>, value:u128, amount:u128, width:usize, context:&[u8], rng:&mut R
+) -> RangeProof<u128> {
+    let q = subgroup_order(cipher.prime);
+    let remain = cipher.minus(balance.current, cipher.encode(balance.pubkey, amount, 0));
+    range_proof(cipher, balance.pubkey, remain, q.minus(value, amount), width, Opening::SecretKey(secret), context, rng)
+}
+
+/**
+ * Debit of lock_encrypted and confidential_transfer: encode amount under
+ * the owner's key and prove both it and the rest of value within range.
+ */
+pub fn debit<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, secret:u128, balance:&CipherText<u128>, value:u128, amount:u128, width:usize, context:&[u8], rng:&mut R
+) -> Debit {
+    let q = subgroup_order(cipher.prime);
+    let rand = rng() % q;
+    let delta = cipher.encode(balance.pubkey, amount, rand);
+    let remain = cipher.minus(balance.current, delta);
+    let proof = range_proof(cipher, balance.pubkey, delta, amount, width, Opening::Randomness(rand), context, rng);
+    let remain_proof = range_proof(cipher, balance.pubkey, remain, q.minus(value, amount), width, Opening::SecretKey(secret), context, rng);
+    Debit { delta, rand, proof, remain_proof }
+}
+
+/**
+ * Tag the proof of what remains of current, as lock_balance, unshield
+ * and debit build it, with the epoch tag of secret. base is
+ * Module::epoch_generator, the pallet expects every remain proof tagged.
+ */
+pub fn tag_remain<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, base:u128, secret:u128, range:RangeProof<u128>, context:&[u8], rng:&mut R
+) -> TaggedRange<u128> {
+    let key = cipher.prime.power(cipher.gamma, secret);
+    let tag = cipher.prime.power(base, secret);
+    let proof = sigma::prove(cipher, &tag_statement(cipher, base, key, tag), &secret, context, rng);
+    TaggedRange { range, tag, proof }
+}
+
+/**
+ * src under src_key and dest under dest_key both encode amount,
+ * with the randomness src_rand and dest_rand.
+ */
+pub fn equality_proof<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>,
+    src_key:u128, src_rand:u128,
+    dest_key:u128, dest_rand:u128,
+    amount:u128, context:&[u8], rng:&mut R
+) -> EqualityProof<u128> {
+    let q = subgroup_order(cipher.prime);
+    let src = cipher.encode(src_key, amount, src_rand);
+    let dest = cipher.encode(dest_key, amount, dest_rand);
+    let (kb, k1, k2) = (rng() % q, rng() % q, rng() % q);
+    let commit_src = cipher.encode(src_key, kb, k1);
+    let commit_dest = cipher.encode(dest_key, kb, k2);
+    let c = cipher.challenge(&[(src_key, dest_key), src, dest, commit_src, commit_dest], context);
+    EqualityProof {
+        commit_src,
+        commit_dest,
+        amount: q.plus(kb, q.mul(c, amount)),
+        rand_src: q.plus(k1, q.mul(c, src_rand)),
+        rand_dest: q.plus(k2, q.mul(c, dest_rand)),
+    }
+}
+
+/**
+ * Arguments of confidential_transfer sending amount to dest_key.
+ */
+pub fn confidential_transfer<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, secret:u128, balance:&CipherText<u128>, value:u128,
+    dest_key:u128, amount:u128, width:usize, context:&[u8], rng:&mut R
+) -> (Debit, (u128, u128), EqualityProof<u128>) {
+    let q = subgroup_order(cipher.prime);
+    let debit = debit(cipher, secret, balance, value, amount, width, context, rng);
+    let credit_rand = rng() % q;
+    let credit = cipher.encode(dest_key, amount, credit_rand);
+    let proof = equality_proof(cipher, balance.pubkey, debit.rand, dest_key, credit_rand, amount, context, rng);
+    (debit, credit, proof)
+}
+
+/**
+ * Authorise an unsigned transfer by proving knowledge of secret,
+ * context must be Module::authorisation_context of the transfer.
+ */
+pub fn authorise<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, secret:u128, context:&[u8], rng:&mut R
+) -> SigmaProof<u128> {
+    let key = cipher.prime.power(cipher.gamma, secret);
+    let statement = sigma::DLog {prime:cipher.prime, base:cipher.gamma, target:key};
+    sigma::prove(cipher, &statement, &secret, context, rng)
+}
+
+/**
+ * Disclose that t under the key of secret encodes exactly value,
+ * context must be Module::disclosure_context of the auditor's challenge.
+ */
+pub fn disclose_exact<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, secret:u128, t:(u128,u128), value:u128, context:&[u8], rng:&mut R
+) -> Disclosure<u128> {
+    let key = cipher.prime.power(cipher.gamma, secret);
+    let proof = sigma::prove(cipher, &cipher.decryption_statement(key, t, value), &secret, context, rng);
+    Disclosure::Exact { amount: value, proof }
+}
+
+/**
+ * Disclose that t under the key of secret, which encodes value,
+ * encodes at least amount.
+ */
+pub fn disclose_at_least<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, secret:u128, t:(u128,u128), value:u128, amount:u128, width:usize, context:&[u8], rng:&mut R
+) -> Disclosure<u128> {
+    let q = subgroup_order(cipher.prime);
+    let key = cipher.prime.power(cipher.gamma, secret);
+    let target = cipher.threshold_target(t, amount);
+    let proof = range_proof(cipher, key, target, q.minus(value, amount), width, Opening::SecretKey(secret), context, rng);
+    Disclosure::AtLeast { amount, proof }
+}
+
+/**
+ * Re-encrypt value, which old under the key of secret encodes, under
+ * new_key in the group of next. Returns the new cipher with its proof.
+ */
+pub fn migrate<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, secret:u128, old:(u128,u128), value:u128,
+    next:&EGICipher<u128>, new_key:u128, width:usize, context:&[u8], rng:&mut R
+) -> ((u128, u128), MigrationProof<u128>) {
+    let q = subgroup_order(cipher.prime);
+    let nq = subgroup_order(next.prime);
+    let old_key = cipher.prime.power(cipher.gamma, secret);
+    let r = rng() % nq;
+    let new = next.encode(new_key, value, r);
+    let mut old_bits = Vec::new();
+    let mut new_bits = Vec::new();
+    let mut witness = Vec::new();
+    let mut weighted = 0;
+    for j in 0..width {
+        let bit = (value >> j) & 1;
+        let (r_old, r_new) = (rng() % q, rng() % nq);
+        old_bits.push(cipher.encode(old_key, bit, r_old));
+        new_bits.push(next.encode(new_key, bit, r_new));
+        witness.push(if bit == 0 { Either::Left((r_old, r_new)) } else { Either::Right((r_old, r_new)) });
+        weighted = nq.plus(weighted, nq.mul(r_new, 1 << j));
+    }
+    let statement = migration_statement(cipher, old_key, old, next, new_key, new, &old_bits, &new_bits);
+    let rests = (secret, nq.minus(r, weighted));
+    let proof = sigma::prove(cipher, &statement, &(witness, rests), &migration_context(next, context), rng);
+    (new, MigrationProof { old_bits, new_bits, proof })
+}
+
+/* Coefficients of the product of two polynomials in x, lowest degree first */
+fn poly_mul(q:u128, a:&[u128], b:&[u128]) -> Vec<u128> {
+    let mut r = Vec::new();
+    r.resize(a.len() + b.len() - 1, 0);
+    for i in 0..a.len() {
+        for j in 0..b.len() {
+            r[i + j] = q.plus(r[i + j], q.mul(a[i], b[j]));
+        }
+    }
+    r
+}
+
+/**
+ * Anonymous transfer of amount from keys[sender] to keys[recv] hidden in
+ * the ring, see ring.rs for the statement. balances must be the ciphers
+ * the pallet holds after rolling over every member, value is what the
+ * sender's balance encodes and tag_base is Module::epoch_generator.
+ */
+pub fn anonymous_transfer<R:FnMut() -> u128>(
+    cipher:&EGICipher<u128>, h:u128, tag_base:u128, keys:&[u128], balances:&[(u128,u128)],
+    secret:u128, sender:usize, value:u128, recv:usize, amount:u128, width:usize, context:&[u8], rng:&mut R
+) -> AnonymousTransfer<u128> {
+    let p = cipher.prime;
+    let q = subgroup_order(p);
+    let g = cipher.gamma;
+    let size = keys.len();
+    let n = size.trailing_zeros() as usize;
+    let mut rnd = || rng() % q;
+
+    let (re, rs) = (rnd(), rnd());
+    let amount_at = |i:usize, at:usize| if i == at { amount } else { 0 };
+    let credits:Vec<u128> = (0..size).map(|i| cipher.encode(keys[i], amount_at(i, recv), re).0).collect();
+    let debits:Vec<u128> = (0..size).map(|i| cipher.encode(keys[i], amount_at(i, sender), rs).0).collect();
+    let credit_rand = p.power(g, re);
+    let debit_rand = p.power(g, rs);
+    let credit_ranges = (0..size).map(|i| range_proof(
+        cipher, keys[i], (credits[i], credit_rand), amount_at(i, recv), width, Opening::Randomness(re), context, &mut rnd
+    )).collect();
+    let remain_value = q.minus(value, amount);
+    let w = rnd();
+    let remain = cipher.encode(h, remain_value, w);
+    let remain_range = range_proof(cipher, h, remain, remain_value, width, Opening::Randomness(w), context, &mut rnd);
+
+    /* commit to the bits l_j of the sender's index */
+    let com = |m:u128, r:u128| p.mul(p.power(g, m), p.power(h, r));
+    let mut bit_secrets = Vec::new();
+    let mut bits = Vec::new();
+    for j in 0..n {
+        let l = ((sender >> j) & 1) as u128;
+        let (r, a, s, t) = (rnd(), rnd(), rnd(), rnd());
+        bits.push(BitCommitment { l: com(l, r), a: com(a, s), b: com(q.mul(l, a), t), f: 0, za: 0, zb: 0 });
+        bit_secrets.push((l, r, a, s, t));
+    }
+    /* p_i(x) = Π_j f_{j,i_j} as coefficients, f_{j,1} = l_j x + a_j and f_{j,0} = x - f_{j,1} */
+    let mut coeffs = Vec::new();
+    for i in 0..size {
+        let mut poly = Vec::new();
+        poly.push(1);
+        for j in 0..n {
+            let (l, _, a, _, _) = bit_secrets[j];
+            let one = [a, l];
+            let zero = [q.minus(0, a), q.minus(1, l)];
+            poly = poly_mul(q, &poly, if (i >> j) & 1 == 1 { &one } else { &zero });
+        }
+        coeffs.push(poly);
+    }
+    let next:Vec<(u128,u128)> = (0..size).map(|i| cipher.minus(balances[i], (debits[i], debit_rand))).collect();
+    let kappa:Vec<u128> = (0..n).map(|_| rnd()).collect();
+    let sigma:Vec<u128> = (0..n).map(|_| rnd()).collect();
+    let mut key_corrections = Vec::new();
+    let mut balance_corrections = Vec::new();
+    for k in 0..n {
+        let mut key = p.power(g, kappa[k]);
+        let mut left = p.power(g, q.mul(secret, sigma[k]));
+        let mut right = p.power(g, sigma[k]);
+        for i in 0..size {
+            key = p.mul(key, p.power(keys[i], coeffs[i][k]));
+            left = p.mul(left, p.power(next[i].0, coeffs[i][k]));
+            right = p.mul(right, p.power(next[i].1, coeffs[i][k]));
+        }
+        key_corrections.push(key);
+        balance_corrections.push((left, right));
+    }
+
+    let tag = p.power(tag_base, secret);
+    let x = fiat_shamir(cipher, &(
+        context, h, tag_base, keys, balances,
+        &credits, credit_rand,
+        &debits, debit_rand,
+        remain, tag,
+        bits.iter().map(|b| (b.l, b.a, b.b)).collect::<Vec<_>>(),
+        &key_corrections, &balance_corrections,
+    ));
+    for j in 0..n {
+        let (l, r, a, s, t) = bit_secrets[j];
+        let f = q.plus(q.mul(l, x), a);
+        bits[j].f = f;
+        bits[j].za = q.plus(q.mul(r, x), s);
+        bits[j].zb = q.plus(q.mul(r, q.minus(x, f)), t);
+    }
+    let mut proof = AnonymousProof { bits, key_corrections, balance_corrections, ..Default::default() };
+    let polys = cipher.index_polynomials(x, &proof.bits);
+    let mut x_pow = Vec::new();
+    x_pow.push(1);
+    for k in 0..n {
+        x_pow.push(q.mul(x_pow[k], x));
+    }
+    let x_n = x_pow[n];
+
+    /* every other member proves a zero debit, the sender its index; the other side is simulated */
+    let mut nonces = Vec::new();
+    for i in 0..size {
+        let mut member = MemberProof::default();
+        if i != sender {
+            let k = rnd();
+            let c1 = rnd();
+            member.zero_commit = (p.power(g, k), p.power(keys[i], k));
+            for j in 0..n {
+                let opened = if (i >> j) & 1 == 1 { p.div(proof.bits[j].l, g) } else { proof.bits[j].l };
+                let z = rnd();
+                member.index_commits.push(p.div(p.power(h, z), p.power(opened, c1)));
+                member.index_responses.push(z);
+            }
+            nonces.push((k, c1, Vec::new()));
+        } else {
+            let c0 = rnd();
+            let z = rnd();
+            member.zero_challenge = c0;
+            member.zero_response = z;
+            member.zero_commit = (
+                p.div(p.power(g, z), p.power(debit_rand, c0)),
+                p.div(p.power(keys[i], z), p.power(debits[i], c0)),
+            );
+            let ks:Vec<u128> = (0..n).map(|_| rnd()).collect();
+            member.index_commits = ks.iter().map(|k| p.power(h, *k)).collect();
+            nonces.push((0, c0, ks));
+        }
+        proof.members.push(member);
+    }
+
+    /* the second component of what the verifier reduces the new balances to */
+    let mut right = 1;
+    for i in 0..size {
+        right = p.mul(right, p.power(next[i].1, polys[i]));
+    }
+    for k in 0..n {
+        right = p.div(right, p.power(proof.balance_corrections[k].1, x_pow[k]));
+    }
+    let (kx, ku, kt) = (rnd(), rnd(), rnd());
+    proof.link_commit = (p.mul(p.power(right, kx), p.power(h, ku)), p.power(g, q.plus(q.mul(kx, x_n), kt)));
+    proof.tag_commit = p.power(tag_base, kx);
+    let keys_prod = keys.iter().fold(1, |acc, y| p.mul(acc, *y));
+    let ks = rnd();
+    proof.sum_commit = (p.power(g, ks), p.power(keys_prod, ks));
+
+    let c = fiat_shamir(cipher, &(
+        x, &proof.members.iter().map(|m| (m.zero_commit, &m.index_commits)).collect::<Vec<_>>(),
+        proof.link_commit, proof.tag_commit, proof.sum_commit,
+    ));
+    let u = q.minus(0, q.mul(w, x_n));
+    let t = (0..n).fold(0, |acc, k| q.minus(acc, q.mul(kappa[k], x_pow[k])));
+    proof.link_response = (q.plus(kx, q.mul(c, secret)), q.plus(ku, q.mul(c, u)), q.plus(kt, q.mul(c, t)));
+    proof.sum_response = q.plus(ks, q.mul(c, q.minus(re, rs)));
+    for (i, (k, simulated, ks)) in nonces.into_iter().enumerate() {
+        let member = &mut proof.members[i];
+        if i != sender {
+            let c0 = q.minus(c, simulated);
+            member.zero_challenge = c0;
+            member.zero_response = q.plus(k, q.mul(c0, rs));
+        } else {
+            let c1 = q.minus(c, simulated);
+            member.index_responses = (0..n).map(|j| q.plus(ks[j], q.mul(c1, bit_secrets[j].1))).collect();
+        }
+    }
+
+    AnonymousTransfer { credits, credit_rand, debits, debit_rand, credit_ranges, remain, remain_range, tag, proof }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::balance::CipherBalance;
+    use crate::proof::migration::migrated;
+    use crate::primering::in_subgroup;
+    use crate::proof::tag::{epoch_generator, tagged_range, tagged_equations};
+
+    /* a safe prime, 7 has order q = (P - 1) / 2 */
+    const P:u128 = 2147483579;
+    const WIDTH:usize = 4;
+    const CTX:&[u8] = b"alice";
+
+    fn cipher() -> EGICipher<u128> {
+        EGICipher { gamma: 7, prime: P }
+    }
+
+    /* Park-Miller, only good enough for tests */
+    fn rng() -> impl FnMut() -> u128 {
+        let mut seed = 2020u128;
+        move || { seed = P.mul(seed, 48271); seed }
+    }
+
+    #[test]
+    fn bit_and_range() {
+        let c = cipher();
+        let mut rng = rng();
+        let x = rng();
+        let y = P.power(7, x);
+        for bit in 0..2 {
+            let r = rng();
+            let proof = bit_proof(&c, y, bit, r, CTX, &mut rng);
+            assert!(c.bit_proof(y, c.encode(y, bit, r), CTX, &proof));
+            assert!(!c.bit_proof(y, c.encode(y, 2, r), CTX, &proof));
+        }
+        for amount in 0..(1 << WIDTH) {
+            let r = rng();
+            let t = c.encode(y, amount, r);
+            let by_rand = range_proof(&c, y, t, amount, WIDTH, Opening::Randomness(r), CTX, &mut rng);
+            let by_key = range_proof(&c, y, t, amount, WIDTH, Opening::SecretKey(x), CTX, &mut rng);
+            assert!(c.within_exp(y, t, WIDTH, CTX, &by_rand));
+            assert!(c.within_exp(y, t, WIDTH, CTX, &by_key));
+            let mut bad = by_key.clone();
+            bad.bits[0] = c.plus(bad.bits[0], c.encode(y, 1, 0));
+            assert!(!c.within_exp(y, t, WIDTH, CTX, &bad));
+        }
+        /* an amount out of range has no valid decomposition */
+        let r = rng();
+        let t = c.encode(y, 1 << WIDTH, r);
+        assert!(!c.within_exp(y, t, WIDTH, CTX, &range_proof(&c, y, t, 1 << WIDTH, WIDTH, Opening::Randomness(r), CTX, &mut rng)));
+        /* the width is the verifier's, not the prover's */
+        let wide = range_proof(&c, y, t, 1 << WIDTH, WIDTH + 1, Opening::Randomness(r), CTX, &mut rng);
+        assert!(c.within_exp(y, t, WIDTH + 1, CTX, &wide));
+        assert!(!c.within_exp(y, t, WIDTH, CTX, &wide));
+        assert!(!c.range_fits(30));
+    }
+
+    #[test]
+    fn lock_and_transfer() {
+        let c = cipher();
+        let mut rng = rng();
+        let (x1, x2) = (rng(), rng());
+        let (y1, y2) = (P.power(7, x1), P.power(7, x2));
+        let balance = CipherText::make(&c, y1, 12, rng());
+
+        let proof = lock_balance(&c, x1, &balance, 12, 5, WIDTH, CTX, &mut rng);
+        let remain = c.minus(balance.current, c.encode(y1, 5, balance.rel));
+        assert!(c.within_exp(y1, remain, WIDTH, CTX, &proof));
+        let other = c.minus(balance.current, c.encode(y1, 4, balance.rel));
+        assert!(!c.within_exp(y1, other, WIDTH, CTX, &proof));
+        /* replayed by somebody else or under a later nonce */
+        assert!(!c.within_exp(y1, remain, WIDTH, b"bob", &proof));
+
+        let proof = unshield(&c, x1, &balance, 12, 12, WIDTH, CTX, &mut rng);
+        let remain = c.minus(balance.current, c.encode(y1, 12, 0));
+        assert!(c.within_exp(y1, remain, WIDTH, CTX, &proof));
+        let proof = unshield(&c, x1, &balance, 12, 13, WIDTH, CTX, &mut rng);
+        let remain = c.minus(balance.current, c.encode(y1, 13, 0));
+        assert!(!c.within_exp(y1, remain, WIDTH, CTX, &proof));
+
+        let (debit, credit, eq) = confidential_transfer(&c, x1, &balance, 12, y2, 5, WIDTH, CTX, &mut rng);
+        assert!(c.within_exp(y1, debit.delta, WIDTH, CTX, &debit.proof));
+        assert!(c.within_exp(y1, c.minus(balance.current, debit.delta), WIDTH, CTX, &debit.remain_proof));
+        assert!(c.equal_amount(y1, debit.delta, y2, credit, CTX, &eq));
+        assert!(!c.equal_amount(y1, debit.delta, y2, credit, b"bob", &eq));
+        assert!(!c.equal_amount(y1, debit.delta, y2, c.plus(credit, c.encode(y2, 1, 0)), CTX, &eq));
+        let mut bad = eq.clone();
+        bad.amount += 1;
+        assert!(!c.equal_amount(y1, debit.delta, y2, credit, CTX, &bad));
+
+        /* overdrawing leaves a remainder out of range */
+        let debit = super::debit(&c, x1, &balance, 12, 13, WIDTH, CTX, &mut rng);
+        assert!(!c.within_exp(y1, c.minus(balance.current, debit.delta), WIDTH, CTX, &debit.remain_proof));
+    }
+
+    #[test]
+    fn disclosure() {
+        let c = cipher();
+        let mut rng = rng();
+        let x = rng();
+        let y = P.power(7, x);
+        let t = c.encode(y, 9, rng());
+
+        let exact = disclose_exact(&c, x, t, 9, CTX, &mut rng);
+        assert!(c.disclose(y, t, WIDTH, CTX, &exact));
+        assert!(!c.disclose(y, t, WIDTH, b"bob", &exact));
+        assert!(!c.disclose(y, c.plus(t, c.encode(y, 1, 0)), WIDTH, CTX, &exact));
+        /* a wrong amount has no valid proof */
+        assert!(!c.disclose(y, t, WIDTH, CTX, &disclose_exact(&c, x, t, 8, CTX, &mut rng)));
+
+        for amount in 0..10 {
+            let at_least = disclose_at_least(&c, x, t, 9, amount, WIDTH, CTX, &mut rng);
+            assert!(c.disclose(y, t, WIDTH, CTX, &at_least));
+        }
+        let over = disclose_at_least(&c, x, t, 9, 10, WIDTH, CTX, &mut rng);
+        assert!(!c.disclose(y, t, WIDTH, CTX, &over));
+        let mut bad = disclose_at_least(&c, x, t, 9, 5, WIDTH, CTX, &mut rng);
+        if let Disclosure::AtLeast { ref mut amount, .. } = bad {
+            *amount = 6;
+        }
+        assert!(!c.disclose(y, t, WIDTH, CTX, &bad));
+    }
+
+    #[test]
+    fn authorisation() {
+        let c = cipher();
+        let mut rng = rng();
+        let x = rng();
+        let y = P.power(7, x);
+        let statement = sigma::DLog {prime:P, base:7, target:y};
+
+        let proof = authorise(&c, x, CTX, &mut rng);
+        assert!(sigma::verify(&c, &statement, CTX, &proof));
+        /* bound to the call it authorises */
+        assert!(!sigma::verify(&c, &statement, b"bob", &proof));
+        /* only the owner of the key can authorise */
+        let other = authorise(&c, rng(), CTX, &mut rng);
+        assert!(!sigma::verify(&c, &statement, CTX, &other));
+    }
+
+    #[test]
+    fn migration() {
+        let c = cipher();
+        /* the largest safe prime below 2^61, 3 has order q */
+        let next = EGICipher { gamma: 3, prime: 2305843009213691579 };
+        let mut rng = rng();
+        let (x, x2) = (rng(), rng());
+        let y = P.power(7, x);
+        let y2 = next.prime.power(next.gamma, x2);
+        let old = c.encode(y, 11, rng());
+
+        let (new, proof) = migrate(&c, x, old, 11, &next, y2, WIDTH, CTX, &mut rng);
+        assert!(migrated(&c, y, old, &next, y2, new, WIDTH, CTX, &proof));
+        assert!(!migrated(&c, y, old, &next, y2, new, WIDTH, b"bob", &proof));
+        assert!(!migrated(&c, y, old, &next, y2, next.plus(new, next.encode(y2, 1, 0)), WIDTH, CTX, &proof));
+        /* re-encrypting a different amount has no valid proof */
+        let (more, forged) = migrate(&c, x, old, 12, &next, y2, WIDTH, CTX, &mut rng);
+        assert!(!migrated(&c, y, old, &next, y2, more, WIDTH, CTX, &forged));
+        let mut bad = proof.clone();
+        bad.new_bits.swap(0, 1);
+        assert!(!migrated(&c, y, old, &next, y2, new, WIDTH, CTX, &bad));
+    }
+
+    #[test]
+    fn anonymous_roundtrip() {
+        let c = cipher();
+        let mut rng = rng();
+        let h = P.power(7, 123456789);
+        let base = epoch_generator(&c, b"epoch");
+        for n in 1..4 {
+            let size = 1 << n;
+            let secrets:Vec<u128> = (0..size).map(|_| rng()).collect();
+            let keys:Vec<u128> = secrets.iter().map(|x| P.power(7, *x)).collect();
+            let values:Vec<u128> = (0..size).map(|i| 8 + i as u128).collect();
+            let balances:Vec<(u128,u128)> = (0..size).map(|i| c.encode(keys[i], values[i], rng())).collect();
+            let sender = size - 1;
+
+            let t = anonymous_transfer(&c, h, base, &keys, &balances, secrets[sender], sender, values[sender], 0, 5, WIDTH, CTX, &mut rng);
+            assert!(c.anonymous_transfer(h, base, &keys, &balances, WIDTH, CTX, &t));
+            assert!(!c.anonymous_transfer(h, base, &keys, &balances, WIDTH, b"bob", &t));
+
+            let mut bad = t.clone();
+            bad.debits[0] = P.mul(bad.debits[0], 7);
+            assert!(!c.anonymous_transfer(h, base, &keys, &balances, WIDTH, CTX, &bad));
+            let mut bad = t.clone();
+            bad.proof.link_response.0 += 1;
+            assert!(!c.anonymous_transfer(h, base, &keys, &balances, WIDTH, CTX, &bad));
+            let mut bad = t.clone();
+            bad.credits.swap(0, 1);
+            assert!(!c.anonymous_transfer(h, base, &keys, &balances, WIDTH, CTX, &bad));
+            /* the tag is the sender's, and of this epoch */
+            assert_eq!(t.tag, P.power(base, secrets[sender]));
+            let mut bad = t.clone();
+            bad.tag = P.power(base, secrets[0]);
+            assert!(!c.anonymous_transfer(h, base, &keys, &balances, WIDTH, CTX, &bad));
+            /* -u would be a tag of its own, it is outside the group */
+            let mut bad = t.clone();
+            bad.tag = P - t.tag;
+            assert!(!c.anonymous_transfer(h, base, &keys, &balances, WIDTH, CTX, &bad));
+            assert!(!c.anonymous_transfer(h, epoch_generator(&c, b"next"), &keys, &balances, WIDTH, CTX, &t));
+
+            /* a wrong secret key can not spend from the ring */
+            let forged = anonymous_transfer(&c, h, base, &keys, &balances, secrets[0], sender, values[sender], 0, 5, WIDTH, CTX, &mut rng);
+            assert!(!c.anonymous_transfer(h, base, &keys, &balances, WIDTH, CTX, &forged));
+        }
+    }
+
+    #[test]
+    fn tagged_remain() {
+        let c = cipher();
+        let mut rng = rng();
+        let base = epoch_generator(&c, b"epoch");
+        let (x1, x2) = (rng(), rng());
+        let y1 = P.power(7, x1);
+        let balance = CipherText::make(&c, y1, 12, rng());
+        let remain = c.minus(balance.current, c.encode(y1, 5, balance.rel));
+
+        let range = lock_balance(&c, x1, &balance, 12, 5, WIDTH, CTX, &mut rng);
+        let tagged = tag_remain(&c, base, x1, range.clone(), CTX, &mut rng);
+        assert!(tagged_range(&c, base, y1, remain, WIDTH, CTX, &tagged));
+        assert!(!tagged_range(&c, base, y1, remain, WIDTH, b"bob", &tagged));
+        /* a tag of another epoch or another key does not pass for this one */
+        assert!(!tagged_range(&c, epoch_generator(&c, b"next"), y1, remain, WIDTH, CTX, &tagged));
+        let mut bad = tagged.clone();
+        bad.tag = P.power(base, x2);
+        assert!(!tagged_range(&c, base, y1, remain, WIDTH, CTX, &bad));
+        /* nor does -u, whatever the challenge */
+        assert!(in_subgroup(P, base));
+        let mut bad = tagged.clone();
+        bad.tag = P - tagged.tag;
+        assert!(!tagged_range(&c, base, y1, remain, WIDTH, CTX, &bad));
+        assert!(!tagged_equations(&c, base, y1, remain, WIDTH, CTX, &bad, &mut Vec::new()));
+        let other = tag_remain(&c, base, x2, range, CTX, &mut rng);
+        assert!(!tagged_range(&c, base, y1, remain, WIDTH, CTX, &other));
+    }
+}
@@ -0,0 +1,283 @@
+use codec::{Encode, Decode};
+use frame_support::{
+    dispatch::{Vec},
+};
+use sp_runtime::RuntimeDebug;
+
+use crate::cipher::{
+    EGICipher,
+    CipherFunctor,
+};
+use crate::primering::{
+    PrimeRing,
+    subgroup_order,
+    in_subgroup,
+};
+use crate::proof::{
+    CipherProof,
+    RangeProof,
+    fiat_shamir,
+};
+
+//
+// Anonymous transfer over a ring of N = 2^n accounts.
+//
+// Every ring member i with key y_i and current balance C_i receives
+// a credit E_i = (γ^e_i * y_i^re, γ^re) and a debit
+// S_i = (γ^s_i * y_i^rs, γ^rs), both into pending. The receiver gets
+// e_i = b and the sender s_i = b, all the others are zero.
+//
+// Let l be the sender's index, the prover shows
+//  - every E_i is within range (nobody loses value by a credit),
+//  - Σ e_i = Σ s_i (no value is created),
+//  - for every i, either S_i encodes zero or i == l, where l is
+//    committed bit by bit as in Groth-Kohlweiss,
+//  - it knows x_l with y_l = γ^x_l and C_l / S_l encodes the value
+//    committed in remain, which is within range,
+//  - the tag u is g_e^x_l for the same x_l (see proof/tag.rs).
+//
+// The last statement only holds for index l, it is checked through
+// the one-out-of-many polynomials p_i(x) = Π_j f_{j,i_j} which have
+// degree n for i == l and a lower degree otherwise.
+//
+// Commitments use Com(m, r) = γ^m * h^r where h is a second
+// generator whose logarithm to γ is unknown.
+//
+
+/**
+ * Commitments to the j-th bit l_j of the secret index, a blinding a_j
+ * and l_j * a_j, with the responses to the challenge x.
+ */
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct BitCommitment<T> {
+    pub l: T,
+    pub a: T,
+    pub b: T,
+    /* l_j * x + a_j */
+    pub f: T,
+    pub za: T,
+    pub zb: T,
+}
+
+/**
+ * Per member OR proof: S_i encodes zero or i is the committed index.
+ */
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct MemberProof<T> {
+    /* (γ^k, y_i^k) for the knowledge of rs */
+    pub zero_commit: (T, T),
+    pub zero_challenge: T,
+    pub zero_response: T,
+    /* h^k_j for the opening of each bit commitment to the bits of i */
+    pub index_commits: Vec<T>,
+    pub index_responses: Vec<T>,
+}
+
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct AnonymousProof<T> {
+    pub bits: Vec<BitCommitment<T>>,
+    /* Π_i y_i^p_{i,k} * γ^κ_k */
+    pub key_corrections: Vec<T>,
+    /* Π_i N_i.0^p_{i,k} * γ^(x_l * σ_k), Π_i N_i.1^p_{i,k} * γ^σ_k */
+    pub balance_corrections: Vec<(T, T)>,
+    pub members: Vec<MemberProof<T>>,
+    /* commitments and responses for x_l, -w * x^n and -Σ κ_k * x^k */
+    pub link_commit: (T, T),
+    /* g_e^k with the nonce k of x_l in link_commit */
+    pub tag_commit: T,
+    pub link_response: (T, T, T),
+    /* (γ^k, Y^k) for re - rs where Y = Π_i y_i */
+    pub sum_commit: (T, T),
+    pub sum_response: T,
+}
+
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct AnonymousTransfer<T> {
+    pub credits: Vec<T>,
+    pub credit_rand: T,
+    pub debits: Vec<T>,
+    pub debit_rand: T,
+    /* range proof of each credit under the member's key */
+    pub credit_ranges: Vec<RangeProof<T>>,
+    /* cipher of the sender's remaining balance under h */
+    pub remain: (T, T),
+    pub remain_range: RangeProof<T>,
+    /* the sender's epoch tag */
+    pub tag: T,
+    pub proof: AnonymousProof<T>,
+}
+
+pub trait RingProof<T> {
+    /* Evaluate p_i(x) for i in 0..2^n from the bit responses */
+    fn index_polynomials(&self, x:T, bits:&[BitCommitment<T>]) -> Vec<T>;
+    fn anonymous_transfer(&self, h:T, tag_base:T, keys:&[T], balances:&[(T,T)], width:usize, context:&[u8], transfer:&AnonymousTransfer<T>) -> bool;
+}
+
+impl RingProof<u128> for EGICipher<u128> {
+    fn index_polynomials(&self, x:u128, bits:&[BitCommitment<u128>]) -> Vec<u128> {
+        let q = subgroup_order(self.prime);
+        let mut polys = Vec::new();
+        polys.push(1);
+        for bit in bits {
+            let one = bit.f % q;
+            let zero = q.minus(x, one);
+            let mut next = Vec::new();
+            for p in polys.iter() {
+                next.push(q.mul(*p, zero));
+            }
+            for p in polys.iter() {
+                next.push(q.mul(*p, one));
+            }
+            polys = next;
+        }
+        polys
+    }
+
+    fn anonymous_transfer(&self, h:u128, tag_base:u128, keys:&[u128], balances:&[(u128,u128)], width:usize, context:&[u8], transfer:&AnonymousTransfer<u128>) -> bool {
+        let p = self.prime;
+        let q = subgroup_order(p);
+        let g = self.gamma;
+        let proof = &transfer.proof;
+        let n = proof.bits.len();
+        let size = keys.len();
+
+        if n == 0 || n >= 32 || size != 1 << n
+            || balances.len() != size
+            || transfer.credits.len() != size
+            || transfer.debits.len() != size
+            || transfer.credit_ranges.len() != size
+            || proof.key_corrections.len() != n
+            || proof.balance_corrections.len() != n
+            || proof.members.len() != size {
+            return false;
+        }
+
+        /* every element lies in the group of prime order q, see proof/sigma.rs */
+        let mut elements = Vec::new();
+        elements.extend_from_slice(&[h, tag_base, transfer.credit_rand, transfer.debit_rand, transfer.tag]);
+        elements.extend_from_slice(&[transfer.remain.0, transfer.remain.1]);
+        elements.extend_from_slice(keys);
+        for b in balances.iter() {
+            elements.extend_from_slice(&[b.0, b.1]);
+        }
+        elements.extend_from_slice(&transfer.credits);
+        elements.extend_from_slice(&transfer.debits);
+        for bit in proof.bits.iter() {
+            elements.extend_from_slice(&[bit.l, bit.a, bit.b]);
+        }
+        elements.extend_from_slice(&proof.key_corrections);
+        for b in proof.balance_corrections.iter() {
+            elements.extend_from_slice(&[b.0, b.1]);
+        }
+        for member in proof.members.iter() {
+            elements.extend_from_slice(&[member.zero_commit.0, member.zero_commit.1]);
+            elements.extend_from_slice(&member.index_commits);
+        }
+        elements.extend_from_slice(&[proof.link_commit.0, proof.link_commit.1, proof.tag_commit]);
+        elements.extend_from_slice(&[proof.sum_commit.0, proof.sum_commit.1]);
+        if !elements.iter().all(|x| in_subgroup(p, *x)) {
+            return false;
+        }
+
+        /* credits are within range, so a credit never takes value away */
+        for i in 0..size {
+            if !self.within_exp(keys[i], (transfer.credits[i], transfer.credit_rand), width, context, &transfer.credit_ranges[i]) {
+                return false;
+            }
+        }
+        if !self.within_exp(h, transfer.remain, width, context, &transfer.remain_range) {
+            return false;
+        }
+
+        /* c below hashes x, so the context binds both challenges */
+        let x = fiat_shamir(self, &(
+            context, h, tag_base, keys, balances,
+            &transfer.credits, transfer.credit_rand,
+            &transfer.debits, transfer.debit_rand,
+            transfer.remain, transfer.tag,
+            proof.bits.iter().map(|b| (b.l, b.a, b.b)).collect::<Vec<_>>(),
+            &proof.key_corrections, &proof.balance_corrections,
+        ));
+
+        /* Groth-Kohlweiss: each committed l_j is a bit and f_j = l_j * x + a_j */
+        let com = |m:u128, r:u128| p.mul(p.power(g, m), p.power(h, r));
+        for bit in proof.bits.iter() {
+            if p.mul(p.power(bit.l, x), bit.a) != com(bit.f, bit.za)
+                || p.mul(p.power(bit.l, q.minus(x, bit.f)), bit.b) != com(0, bit.zb) {
+                return false;
+            }
+        }
+        let polys = self.index_polynomials(x, &proof.bits);
+        let mut x_pow = Vec::new();
+        x_pow.push(1);
+        for k in 0..n {
+            x_pow.push(q.mul(x_pow[k], x));
+        }
+        let x_n = x_pow[n];
+
+        let c = fiat_shamir(self, &(
+            x, &proof.members.iter().map(|m| (m.zero_commit, &m.index_commits)).collect::<Vec<_>>(),
+            proof.link_commit, proof.tag_commit, proof.sum_commit,
+        ));
+
+        /* For every member, S_i encodes zero or i is the committed index */
+        for i in 0..size {
+            let member = &proof.members[i];
+            if member.index_commits.len() != n || member.index_responses.len() != n {
+                return false;
+            }
+            let c0 = member.zero_challenge % q;
+            let c1 = q.minus(c, c0);
+            if p.power(g, member.zero_response) != p.mul(member.zero_commit.0, p.power(transfer.debit_rand, c0))
+                || p.power(keys[i], member.zero_response) != p.mul(member.zero_commit.1, p.power(transfer.debits[i], c0)) {
+                return false;
+            }
+            for j in 0..n {
+                let opened = if (i >> j) & 1 == 1 { p.div(proof.bits[j].l, g) } else { proof.bits[j].l };
+                if p.power(h, member.index_responses[j]) != p.mul(member.index_commits[j], p.power(opened, c1)) {
+                    return false;
+                }
+            }
+        }
+
+        /* Σ e_i = Σ s_i: Π E_i.0 / Π S_i.0 = Y^(re - rs) and re - rs is known */
+        let keys_prod = keys.iter().fold(1, |acc, y| p.mul(acc, *y));
+        let credit_prod = transfer.credits.iter().fold(1, |acc, e| p.mul(acc, *e));
+        let debit_prod = transfer.debits.iter().fold(1, |acc, s| p.mul(acc, *s));
+        let sum_base = p.div(transfer.credit_rand, transfer.debit_rand);
+        let sum_target = p.div(credit_prod, debit_prod);
+        if p.power(g, proof.sum_response) != p.mul(proof.sum_commit.0, p.power(sum_base, c))
+            || p.power(keys_prod, proof.sum_response) != p.mul(proof.sum_commit.1, p.power(sum_target, c)) {
+            return false;
+        }
+
+        /*
+         * Reduce the sender's new balance N_l = C_l / S_l and its key y_l
+         * through the index polynomials, the corrections cancel all the
+         * terms of degree below n which come from other members.
+         */
+        let mut key_acc = 1;
+        let mut left = 1;
+        let mut right = 1;
+        for i in 0..size {
+            let next = self.minus(balances[i], (transfer.debits[i], transfer.debit_rand));
+            key_acc = p.mul(key_acc, p.power(keys[i], polys[i]));
+            left = p.mul(left, p.power(next.0, polys[i]));
+            right = p.mul(right, p.power(next.1, polys[i]));
+        }
+        for k in 0..n {
+            key_acc = p.div(key_acc, p.power(proof.key_corrections[k], x_pow[k]));
+            left = p.div(left, p.power(proof.balance_corrections[k].0, x_pow[k]));
+            right = p.div(right, p.power(proof.balance_corrections[k].1, x_pow[k]));
+        }
+        /*
+         * left / remain.0^(x^n) = right^x_l * h^(-w * x^n), key_acc = γ^(x_l * x^n - Σ κ_k x^k)
+         * and tag = g_e^x_l, all with the same response for x_l
+         */
+        let left = p.div(left, p.power(transfer.remain.0, x_n));
+        let (s_x, s_u, s_t) = proof.link_response;
+        p.mul(p.power(right, s_x), p.power(h, s_u)) == p.mul(proof.link_commit.0, p.power(left, c))
+            && p.power(g, q.plus(q.mul(s_x, x_n), s_t)) == p.mul(proof.link_commit.1, p.power(key_acc, c))
+            && p.power(tag_base, s_x) == p.mul(proof.tag_commit, p.power(transfer.tag, c))
+    }
+}
@@ -0,0 +1,749 @@
+//
+// Two token instances in one runtime keep apart: genesis, balances,
+// issuance, shielded supply and pool tags of one never show in the other.
+// A call whose origin, proofs or fee do not hold fails before it writes,
+// the roll over of a new epoch included.
+//
+
+use frame_support::{assert_ok, assert_noop, unsigned::ValidateUnsigned, weights::DispatchInfo};
+use sp_runtime::{
+    traits::{OnFinalize, SignedExtension},
+    transaction_validity::InvalidTransaction,
+    DispatchError,
+};
+use system::RawOrigin;
+
+use crate::mock::*;
+use crate::cipher::CipherFunctor;
+use crate::primering::PrimeRing;
+use crate::{
+    prover, BigInt, EGICipher, Error, DefaultInstance, BalanceMap, LastUnsigned, RangeProof, ProofEnvelope, ChargeConfidentialFee,
+    ProofFailure, Statement,
+    SCHEME_EQUALITY, SCHEME_KEY_OWNERSHIP, SCHEME_MIGRATION, SCHEME_RING, SCHEME_SIGMA_RANGE, SCHEME_TAGGED_RANGE,
+};
+
+type TokenError = Error<Test, DefaultInstance>;
+
+fn pair((a, b): (u128, u128)) -> (BigInt, BigInt) {
+    (a.into(), b.into())
+}
+
+/* Park-Miller, the proofs of the tests need no secrecy */
+fn rng() -> impl FnMut() -> u128 {
+    let mut seed = 2020u128;
+    move || { seed = PRIME.mul(seed, 48271); seed }
+}
+
+/* who's current cipher in Token */
+fn current(who: u64) -> (u128, u128) {
+    let current = Token::account_balance(&who).unwrap().current;
+    (current.0.into(), current.1.into())
+}
+
+/* who's pending cipher in Token, if anything is pending */
+fn pending(who: u64) -> Option<(u128, u128)> {
+    Token::pending_state(&who).unwrap().pending.map(|(a, b)| (a.into(), b.into()))
+}
+
+/* who's range proof of what remains of current, tagged for this epoch and bound to nonce */
+fn tagged<R: FnMut() -> u128>(who: u64, nonce: u64, range: RangeProof<u128>, rng: &mut R) -> ProofEnvelope {
+    let base = Token::epoch_generator(&TOKEN_CIPHER);
+    let remain = prover::tag_remain(&TOKEN_CIPHER, base, secret(who), range, &Token::proof_context(&who, nonce), rng);
+    prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_TAGGED_RANGE, nonce, &remain)
+}
+
+/* The Token events of this block, none of them emitted by an extrinsic */
+fn token_events() -> Vec<(Option<u32>, crate::Event<Test, DefaultInstance>)> {
+    System::events().into_iter().filter_map(|record| match record.event {
+        TestEvent::token(event) => Some((None, event)),
+        _ => None,
+    }).collect()
+}
+
+/* t under the key of secret encodes value: t.0 / t.1^x = γ^value */
+fn decrypts(cipher: &EGICipher<u128>, secret: u128, t: (u128, u128), value: u128) -> bool {
+    let p = cipher.prime;
+    p.div(t.0, p.power(t.1, secret)) == p.power(cipher.gamma, value)
+}
+
+#[test]
+fn genesis_is_per_instance() {
+    new_test_ext().execute_with(|| {
+        assert!(Token::account_balance(&2).is_some());
+        assert!(SecondToken::account_balance(&2).is_none());
+        assert!(Token::account_balance(&3).is_none());
+        assert!(SecondToken::account_balance(&3).is_some());
+
+        /* the same secret key, a different key and balance in each token */
+        let (token_key, token_current) = prover::genesis_account(&TOKEN_CIPHER, secret(1), 100, 6);
+        let (second_key, second_current) = prover::genesis_account(&SECOND_CIPHER, secret(1), 50, 6);
+        let token_balance = Token::account_balance(&1).unwrap();
+        let second_balance = SecondToken::account_balance(&1).unwrap();
+        assert_eq!(token_balance.pubkey, BigInt::from(token_key));
+        assert_eq!(token_balance.current, pair(token_current));
+        assert_eq!(second_balance.pubkey, BigInt::from(second_key));
+        assert_eq!(second_balance.current, pair(second_current));
+
+        assert_eq!(Token::cipher_params().generator, BigInt::from(TOKEN_CIPHER.gamma));
+        assert_eq!(SecondToken::cipher_params().generator, BigInt::from(SECOND_CIPHER.gamma));
+        assert_eq!(Token::confidential_issuance(), 300);
+        assert_eq!(SecondToken::confidential_issuance(), 120);
+    });
+}
+
+#[test]
+fn mint_credits_one_instance() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(SecondToken::mint(RawOrigin::Root.into(), 1, 10));
+
+        let key = prover::genesis_account(&SECOND_CIPHER, secret(1), 0, 0).0;
+        let credit = SECOND_CIPHER.encode(key, 10, 0);
+        let pending = SecondToken::pending_state(&1).unwrap().pending;
+        assert_eq!(pending, Some(pair(credit)));
+        assert_eq!(SecondToken::confidential_issuance(), 130);
+
+        assert_eq!(Token::pending_state(&1).unwrap().pending, None);
+        assert_eq!(Token::confidential_issuance(), 300);
+    });
+}
+
+#[test]
+fn shield_moves_value_into_one_instance() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(Token::shield(Origin::signed(2), 40));
+
+        assert_eq!(Balances::free_balance(2), 960);
+        assert_eq!(Token::shielded_supply(), 40);
+        assert!(Token::pending_state(&2).unwrap().pending.is_some());
+        assert_eq!(SecondToken::shielded_supply(), 0);
+        assert_eq!(SecondToken::total_supply(), Token::total_supply() - 40);
+    });
+}
+
+#[test]
+fn unshield_returns_what_was_shielded() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::shield(Origin::signed(2), 100));
+        /* the shielded 100 is pending until the next epoch */
+        System::set_block_number(EpochLength::get());
+        let balance = Token::balance_preview(&TOKEN_CIPHER, &2).unwrap();
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 300, 60, 16, &Token::proof_context(&2, 0), &mut rng);
+        assert_ok!(Token::unshield(Origin::signed(2), 60, tagged(2, 0, range, &mut rng)));
+
+        assert_eq!(Balances::free_balance(2), 960);
+        assert_eq!(Token::shielded_supply(), 40);
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 240));
+        /* only what was shielded comes back */
+        let later = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_TAGGED_RANGE, 1, &0u128);
+        assert_noop!(Token::unshield(Origin::signed(2), 41, later), TokenError::InsufficientShielded);
+    });
+}
+
+#[test]
+fn proofs_are_bound_to_the_instance() {
+    new_test_ext().execute_with(|| {
+        assert_ne!(Token::nonce_tag(&1, 0), SecondToken::nonce_tag(&1, 0));
+        assert_ne!(Token::proof_context(&1, 0), SecondToken::proof_context(&1, 0));
+    });
+}
+
+#[test]
+fn instances_roll_over_apart() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 1, 10));
+        assert_ok!(SecondToken::mint(RawOrigin::Root.into(), 1, 10));
+        System::set_block_number(EpochLength::get());
+
+        let balance = Token::balance_preview(&TOKEN_CIPHER, &1).unwrap();
+        let range = prover::unshield(&TOKEN_CIPHER, secret(1), &balance, 110, 10, 16, &Token::proof_context(&1, 0), &mut rng);
+        assert_ok!(Token::burn(RawOrigin::Root.into(), 1, 10, tagged(1, 0, range, &mut rng)));
+        assert_eq!(pending(1), None);
+        assert!(decrypts(&TOKEN_CIPHER, secret(1), current(1), 100));
+        /* SecondToken's credit waits for a call of SecondToken */
+        assert!(SecondToken::pending_state(&1).unwrap().due);
+        assert_eq!(SecondToken::confidential_nonce(&1), 0);
+    });
+}
+
+#[test]
+fn rotation_carries_pending_over() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 1, 10));
+        let old = TOKEN_CIPHER.plus(current(1), pending(1).unwrap());
+        assert!(decrypts(&TOKEN_CIPHER, secret(1), old, 110));
+
+        /* still in the epoch of the credit, no roll over merged it yet */
+        let new_secret = secret(1) + 1;
+        let key = PRIME.power(TOKEN_CIPHER.gamma, new_secret);
+        let (rotated, proof) = prover::migrate(
+            &TOKEN_CIPHER, secret(1), old, 110, &TOKEN_CIPHER, key, 16, &Token::proof_context(&1, 0), &mut rng,
+        );
+        let envelope = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_MIGRATION, 0, &proof);
+        assert_ok!(Token::rotate_key(Origin::signed(1), key, rotated, envelope));
+
+        assert_eq!(Token::account_balance(&1).unwrap().pubkey, BigInt::from(key));
+        assert_eq!(pending(1), None);
+        assert!(decrypts(&TOKEN_CIPHER, new_secret, current(1), 110));
+    });
+}
+
+#[test]
+fn rotation_must_cover_pending() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 1, 10));
+        let key = PRIME.power(TOKEN_CIPHER.gamma, secret(1) + 1);
+        let (rotated, proof) = prover::migrate(
+            &TOKEN_CIPHER, secret(1), current(1), 100, &TOKEN_CIPHER, key, 16, &Token::proof_context(&1, 0), &mut rng,
+        );
+        let envelope = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_MIGRATION, 0, &proof);
+        assert_noop!(Token::rotate_key(Origin::signed(1), key, rotated, envelope), TokenError::InvalidProof);
+        assert_noop!(Token::set_pubkey(Origin::signed(1), key), TokenError::AccountExists);
+    });
+}
+
+#[test]
+fn created_account_decrypts_credits() {
+    new_test_ext().execute_with(|| {
+        let key = PRIME.power(TOKEN_CIPHER.gamma, secret(4));
+        /* -key is outside the group of prime order, no proof under it could verify */
+        assert_noop!(Token::set_pubkey(Origin::signed(4), PRIME - key), TokenError::BadParameters);
+        assert_ok!(Token::set_pubkey(Origin::signed(4), key));
+        let balance = Token::account_balance(&4).unwrap();
+        assert_eq!(balance.current, pair((1, 1)));
+        assert_eq!(balance.lock, pair((1, 1)));
+
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 4, 25));
+        assert!(decrypts(&TOKEN_CIPHER, secret(4), TOKEN_CIPHER.plus(current(4), pending(4).unwrap()), 25));
+    });
+}
+
+#[test]
+fn ring_leaves_decoys_current_alone() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        /* no events are kept at genesis */
+        System::set_block_number(1);
+        for who in 3..5 {
+            assert_ok!(Token::set_pubkey(Origin::signed(who), PRIME.power(TOKEN_CIPHER.gamma, secret(who))));
+        }
+        /* 2 queues a lock against its current, then sits in 1's ring */
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let range = prover::lock_balance(&TOKEN_CIPHER, secret(2), &balance, 200, 50, 16, &Token::proof_context(&2, 0), &mut rng);
+        assert_ok!(Token::lock_balance(Origin::signed(2), 50, tagged(2, 0, range, &mut rng)));
+
+        let ring = [1u64, 2, 3, 4];
+        let balances = ring.iter().map(|who| <BalanceMap<Test, DefaultInstance>>::get(who)).collect::<Vec<_>>();
+        let keys = balances.iter().map(|b| b.pubkey).collect::<Vec<_>>();
+        let currents = balances.iter().map(|b| b.current).collect::<Vec<_>>();
+        let base = Token::epoch_generator(&TOKEN_CIPHER);
+        let transfer = prover::anonymous_transfer(
+            &TOKEN_CIPHER, TOKEN_H, base, &keys, &currents, secret(1), 0, 100, 1, 30, 16,
+            &Token::proof_context(&4, 0), &mut rng,
+        );
+        let envelope = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_RING, 0, &transfer);
+        assert_ok!(Token::anonymous_transfer(Origin::signed(4), ring.to_vec(), envelope));
+
+        /* every member is debited through pending, current stays */
+        assert_eq!(current(1), currents[0]);
+        assert_eq!(current(2), currents[1]);
+        assert!(decrypts(&TOKEN_CIPHER, secret(1), TOKEN_CIPHER.plus(current(1), pending(1).unwrap()), 70));
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), pending(2).unwrap(), 30));
+        /* the event reports what went into each member's pending */
+        let incoming = Token::incoming(token_events(), None, None);
+        assert_eq!(incoming.len(), 4);
+        for credit in incoming {
+            assert_eq!(credit.credit, pair(pending(credit.who).unwrap()));
+        }
+
+        <Token as OnFinalize<u64>>::on_finalize(0);
+        let locked = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), locked.lock, 50));
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), locked.current, 150));
+
+        /* the sender's tag is spent, its current can not pay again this epoch */
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&1);
+        let range = prover::unshield(&TOKEN_CIPHER, secret(1), &balance, 100, 100, 16, &Token::proof_context(&1, 0), &mut rng);
+        assert_noop!(
+            Token::burn(RawOrigin::Root.into(), 1, 100, tagged(1, 0, range, &mut rng)),
+            TokenError::TagSpent
+        );
+    });
+}
+
+#[test]
+fn incoming_picks_credits_by_account_or_key() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 1, 10));
+        assert_ok!(Token::shield(Origin::signed(2), 40));
+        let events = token_events();
+        assert_eq!(Token::incoming(events.clone(), None, None).len(), 2);
+
+        let to_one = Token::incoming(events.clone(), Some(&1), None);
+        assert_eq!(to_one.len(), 1);
+        assert_eq!(to_one[0].credit, pair(pending(1).unwrap()));
+        let key = <BalanceMap<Test, DefaultInstance>>::get(&2).pubkey;
+        let to_key = Token::incoming(events, None, Some(key));
+        assert_eq!(to_key.len(), 1);
+        assert_eq!(to_key[0].who, 2);
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), pending(2).unwrap(), 40));
+    });
+}
+
+#[test]
+fn lock_checks_its_proof() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let context = Token::proof_context(&2, 0);
+        let range = prover::lock_balance(&TOKEN_CIPHER, secret(2), &balance, 200, 50, 16, &context, &mut rng);
+        let base = Token::epoch_generator(&TOKEN_CIPHER);
+        let mut remain = prover::tag_remain(&TOKEN_CIPHER, base, secret(2), range.clone(), &context, &mut rng);
+        remain.range.proof.responses.pop();
+        let malformed = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_TAGGED_RANGE, 0, &remain);
+        assert_noop!(Token::lock_balance(Origin::signed(2), 50, malformed), TokenError::MalformedProof);
+
+        /* of the right shape but for another amount, dropped in on_finalize */
+        assert_ok!(Token::lock_balance(Origin::signed(2), 60, tagged(2, 0, range, &mut rng)));
+        assert_eq!(Token::confidential_nonce(&2), 1);
+        <Token as OnFinalize<u64>>::on_finalize(0);
+        assert_eq!(Token::account_balance(&2).unwrap().lock, pair((1, 1)));
+    });
+}
+
+#[test]
+fn locks_are_released_together() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let range = prover::lock_balance(&TOKEN_CIPHER, secret(2), &balance, 200, 30, 16, &Token::proof_context(&2, 0), &mut rng);
+        assert_ok!(Token::lock_balance(Origin::signed(2), 30, tagged(2, 0, range, &mut rng)));
+        <Token as OnFinalize<u64>>::on_finalize(0);
+        System::set_block_number(1);
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let range = prover::lock_balance(&TOKEN_CIPHER, secret(2), &balance, 170, 20, 16, &Token::proof_context(&2, 1), &mut rng);
+        assert_ok!(Token::lock_balance(Origin::signed(2), 20, tagged(2, 1, range, &mut rng)));
+        <Token as OnFinalize<u64>>::on_finalize(1);
+
+        /* only the total of both locks releases them */
+        assert_noop!(Token::transfer(Origin::signed(2), 30, 1), TokenError::LockMismatch);
+        assert_ok!(Token::transfer(Origin::signed(2), 50, 1));
+        assert_eq!(Token::account_balance(&2).unwrap().lock, pair((1, 1)));
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 150));
+        assert!(decrypts(&TOKEN_CIPHER, secret(1), pending(1).unwrap(), 50));
+    });
+}
+
+#[test]
+fn encrypted_lock_transfers() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let debit = prover::debit(&TOKEN_CIPHER, secret(2), &balance, 200, 50, 16, &Token::proof_context(&2, 0), &mut rng);
+        let proof = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_SIGMA_RANGE, 0, &debit.proof);
+        let remain_proof = tagged(2, 0, debit.remain_proof, &mut rng);
+        assert_ok!(Token::lock_encrypted(Origin::signed(2), debit.delta, proof, remain_proof));
+        assert_eq!(Token::account_balance(&2).unwrap().lock, pair(debit.delta));
+
+        let dest_key = <BalanceMap<Test, DefaultInstance>>::get(&1).pubkey;
+        let credit_rand = rng() % ((PRIME - 1) / 2);
+        let credit = TOKEN_CIPHER.encode(dest_key, 50, credit_rand);
+        let equality = prover::equality_proof(
+            &TOKEN_CIPHER, balance.pubkey, debit.rand, dest_key, credit_rand, 50, &Token::proof_context(&2, 1), &mut rng,
+        );
+        let equality = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_EQUALITY, 1, &equality);
+        assert_ok!(Token::transfer_encrypted(Origin::signed(2), 1, credit, equality));
+
+        assert_eq!(Token::confidential_nonce(&2), 2);
+        assert_eq!(Token::account_balance(&2).unwrap().lock, pair((1, 1)));
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 150));
+        assert!(decrypts(&TOKEN_CIPHER, secret(1), pending(1).unwrap(), 50));
+    });
+}
+
+#[test]
+fn confidential_transfer_moves_value() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let dest_key = <BalanceMap<Test, DefaultInstance>>::get(&1).pubkey;
+        let context = Token::proof_context(&2, 0);
+        let (debit, credit, equality) = prover::confidential_transfer(
+            &TOKEN_CIPHER, secret(2), &balance, 200, dest_key, 80, 16, &context, &mut rng,
+        );
+        let proof = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_SIGMA_RANGE, 0, &debit.proof);
+        let remain_proof = tagged(2, 0, debit.remain_proof, &mut rng);
+
+        /* the equality proof of another transfer does not tie this debit to this credit */
+        let (_, _, wrong) = prover::confidential_transfer(
+            &TOKEN_CIPHER, secret(2), &balance, 200, dest_key, 81, 16, &context, &mut rng,
+        );
+        let wrong = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_EQUALITY, 0, &wrong);
+        assert_noop!(
+            Token::confidential_transfer(Origin::signed(2), 1, debit.delta, credit, proof.clone(), remain_proof.clone(), wrong),
+            TokenError::InvalidProof
+        );
+
+        let equality = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_EQUALITY, 0, &equality);
+        assert_ok!(Token::confidential_transfer(Origin::signed(2), 1, debit.delta, credit, proof, remain_proof, equality));
+        assert_eq!(Token::confidential_nonce(&2), 1);
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 120));
+        assert!(decrypts(&TOKEN_CIPHER, secret(1), pending(1).unwrap(), 80));
+    });
+}
+
+#[test]
+fn clear_amounts_stay_in_range() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let wide = 1u128 << RangeBits::get();
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let context = Token::proof_context(&2, 0);
+        let range = prover::lock_balance(&TOKEN_CIPHER, secret(2), &balance, 200, 50, 16, &context, &mut rng);
+        assert_noop!(Token::lock_balance(Origin::signed(2), wide + 50, tagged(2, 0, range, &mut rng)), TokenError::AmountOutOfRange);
+        assert_noop!(Token::transfer(Origin::signed(2), wide, 1), TokenError::AmountOutOfRange);
+    });
+}
+
+#[test]
+fn pending_survives_the_switch() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 1, 10));
+        assert_ok!(Token::set_cipher_params(
+            RawOrigin::Root.into(), SECOND_CIPHER.gamma, PRIME, SECOND_H, 5,
+        ));
+        let old = TOKEN_CIPHER.plus(current(1), pending(1).unwrap());
+        let key = PRIME.power(SECOND_CIPHER.gamma, secret(1));
+        let (migrated, proof) = prover::migrate(
+            &TOKEN_CIPHER, secret(1), old, 110, &SECOND_CIPHER, key, 16, &Token::proof_context(&1, 0), &mut rng,
+        );
+        let envelope = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_MIGRATION, 0, &proof);
+        assert_ok!(Token::migrate_balance(Origin::signed(1), key, migrated, envelope));
+        assert_eq!(pending(1), None);
+        assert_noop!(Token::mint(RawOrigin::Root.into(), 1, 5), TokenError::MigrationInProgress);
+
+        System::set_block_number(5);
+        <Token as OnFinalize<u64>>::on_finalize(5);
+        /* the first credit after the switch moves the account over and stays pending */
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 1, 5));
+        assert_eq!(Token::account_balance(&1).unwrap().pubkey, BigInt::from(key));
+        assert!(decrypts(&SECOND_CIPHER, secret(1), current(1), 110));
+        assert!(decrypts(&SECOND_CIPHER, secret(1), pending(1).unwrap(), 5));
+    });
+}
+
+
+#[test]
+fn shielded_value_pays_fees() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::shield(Origin::signed(2), 400));
+        System::set_block_number(10);
+        /* more than was ever minted, the rest comes out of what 2 shielded */
+        let balance = Token::balance_preview(&TOKEN_CIPHER, &2).unwrap();
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 600, 350, 16, &Token::proof_context(&2, 0), &mut rng);
+        let fee = ChargeConfidentialFee::<Test, DefaultInstance>::from(350, tagged(2, 0, range, &mut rng));
+        let call = Call::Token(crate::Call::shield(1));
+        assert_ok!(fee.pre_dispatch(&2, &call, DispatchInfo::default(), 0));
+        assert_eq!(Token::confidential_issuance(), 0);
+        assert_eq!(Token::shielded_supply(), 350);
+        assert_eq!(Token::confidential_nonce(&2), 1);
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 250));
+    });
+}
+
+#[test]
+fn bad_unsigned_transfer_stays_out() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let junk = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_EQUALITY, 0, &0u128);
+        let context = Token::authorisation_context(&1, 0, &2, (1, 1), (1, 1), &junk, &junk, &junk);
+        /* 2 authorises a transfer out of 1 */
+        let forged = prover::authorise(&TOKEN_CIPHER, secret(2), &context, &mut rng);
+        let authorisation = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_KEY_OWNERSHIP, 0, &forged);
+        let call = crate::Call::<Test, DefaultInstance>::unsigned_transfer(
+            1, 2, (1, 1), (1, 1), junk.clone(), junk.clone(), junk.clone(), authorisation.clone(),
+        );
+        assert_eq!(
+            <Token as ValidateUnsigned>::pre_dispatch(&call),
+            Err(InvalidTransaction::Custom(TokenError::InvalidProof.as_u8()).into()),
+        );
+        assert_noop!(
+            Token::unsigned_transfer(RawOrigin::None.into(), 1, 2, (1, 1), (1, 1), junk.clone(), junk.clone(), junk, authorisation),
+            TokenError::InvalidProof,
+        );
+    });
+}
+
+#[test]
+fn failed_proofs_abort_calls() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::shield(Origin::signed(2), 100));
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 200, 50, 16, &Token::proof_context(&2, 0), &mut rng);
+        /* the proof is about unshielding 50 */
+        assert_noop!(Token::unshield(Origin::signed(2), 60, tagged(2, 0, range.clone(), &mut rng)), TokenError::InvalidProof);
+        assert_noop!(Token::unshield(Origin::signed(2), 50, tagged(2, 1, range, &mut rng)), TokenError::FutureNonce);
+        let retired = ProofEnvelope::V0(0, [(1, 1); 4]);
+        assert_noop!(Token::unshield(Origin::signed(2), 50, retired.clone()), TokenError::RetiredProof);
+        let equality = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_EQUALITY, 0, &0u128);
+        assert_noop!(Token::unshield(Origin::signed(2), 50, equality.clone()), TokenError::WrongProofScheme);
+        assert_noop!(Token::unshield(Origin::signed(5), 50, retired), TokenError::UnknownAccount);
+        /* nothing is locked */
+        assert_noop!(Token::transfer_encrypted(Origin::signed(2), 1, (1, 1), equality), TokenError::LockMismatch);
+    });
+}
+
+#[test]
+fn roll_over_waits_for_the_checks() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::shield(Origin::signed(2), 100));
+        System::set_block_number(EpochLength::get());
+        /* the proof is built against current with the shielded 100 merged in */
+        let balance = Token::balance_preview(&TOKEN_CIPHER, &2).unwrap();
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 300, 50, 16, &Token::proof_context(&2, 0), &mut rng);
+        assert_noop!(Token::unshield(Origin::signed(2), 60, tagged(2, 0, range.clone(), &mut rng)), TokenError::InvalidProof);
+        assert!(Token::pending_state(&2).unwrap().due);
+
+        assert_ok!(Token::unshield(Origin::signed(2), 50, tagged(2, 0, range, &mut rng)));
+        assert_eq!(pending(2), None);
+        assert_eq!(Token::pending_state(&2).unwrap().last_roll_over, 1);
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 250));
+    });
+}
+
+#[test]
+fn burn_takes_the_owners_proof() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 200, 40, 16, &Token::proof_context(&2, 0), &mut rng);
+        let proof = tagged(2, 0, range, &mut rng);
+        /* the proof of 2 does not burn from 1 */
+        assert_noop!(Token::burn(RawOrigin::Root.into(), 1, 40, proof.clone()), TokenError::InvalidProof);
+        assert_ok!(Token::burn(RawOrigin::Root.into(), 2, 40, proof.clone()));
+        assert_eq!(Token::confidential_issuance(), 260);
+        assert_eq!(Token::confidential_nonce(&2), 1);
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 160));
+        /* nor burns twice */
+        assert_noop!(Token::burn(RawOrigin::Root.into(), 2, 40, proof), TokenError::StaleNonce);
+    });
+}
+
+#[test]
+fn credits_wait_for_the_next_epoch() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::shield(Origin::signed(2), 100));
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 200, 50, 16, &Token::proof_context(&2, 0), &mut rng);
+        /* a credit arriving after the proof was built does not invalidate it */
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 2, 10));
+        assert_ok!(Token::unshield(Origin::signed(2), 50, tagged(2, 0, range, &mut rng)));
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 150));
+        assert!(!Token::pending_state(&2).unwrap().due);
+
+        /* the first call of the next epoch merges both credits */
+        System::set_block_number(EpochLength::get());
+        let balance = Token::balance_preview(&TOKEN_CIPHER, &2).unwrap();
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), balance.current, 260));
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 260, 10, 16, &Token::proof_context(&2, 1), &mut rng);
+        assert_ok!(Token::unshield(Origin::signed(2), 10, tagged(2, 1, range, &mut rng)));
+        assert_eq!(pending(2), None);
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 250));
+    });
+}
+
+#[test]
+fn mint_and_burn_need_root() {
+    new_test_ext().execute_with(|| {
+        let retired = ProofEnvelope::V0(0, [(1, 1); 4]);
+        assert_noop!(Token::mint(Origin::signed(1), 1, 10), DispatchError::BadOrigin);
+        assert_noop!(Token::burn(Origin::signed(1), 1, 10, retired), DispatchError::BadOrigin);
+        assert_eq!(Token::confidential_issuance(), 300);
+    });
+}
+
+#[test]
+#[cfg(not(feature = "dev"))]
+fn reset_balance_is_dev_only() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(Token::reset_balance(Origin::signed(1), 1000), TokenError::DevOnly);
+    });
+}
+
+#[test]
+fn cipher_params_are_governed() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(
+            Token::set_cipher_params(Origin::signed(1), SECOND_CIPHER.gamma, PRIME, SECOND_H, 5),
+            DispatchError::BadOrigin,
+        );
+        /* 2 is no square, it is outside the group of prime order (PRIME - 1) / 2 */
+        assert_noop!(
+            Token::set_cipher_params(RawOrigin::Root.into(), 2, PRIME, SECOND_H, 5),
+            TokenError::BadParameters,
+        );
+        /* 2^31 - 1 is prime, but not safe: its group has elements of small order */
+        assert_noop!(
+            Token::set_cipher_params(RawOrigin::Root.into(), 7, 2147483647, 48271, 5),
+            TokenError::BadParameters,
+        );
+        /* no time left to migrate */
+        assert_noop!(
+            Token::set_cipher_params(RawOrigin::Root.into(), SECOND_CIPHER.gamma, PRIME, SECOND_H, 0),
+            TokenError::BadParameters,
+        );
+        let retired = ProofEnvelope::V0(0, [(1, 1); 4]);
+        assert_noop!(Token::migrate_balance(Origin::signed(1), 1, (1, 1), retired), TokenError::NoMigration);
+    });
+}
+
+#[test]
+fn disclosures_verify_against_current() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let t = <BalanceMap<Test, DefaultInstance>>::get(&2).current;
+        let context = Token::disclosure_context(&2, b"audit");
+        let exact = prover::disclose_exact(&TOKEN_CIPHER, secret(2), t, 200, &context, &mut rng);
+        let at_least = prover::disclose_at_least(&TOKEN_CIPHER, secret(2), t, 200, 150, 16, &context, &mut rng);
+        assert!(Token::verify_disclosure(&2, b"audit", &exact));
+        assert!(Token::verify_disclosure(&2, b"audit", &at_least));
+        /* bound to the account and the auditor's challenge */
+        assert!(!Token::verify_disclosure(&1, b"audit", &exact));
+        assert!(!Token::verify_disclosure(&2, b"other", &at_least));
+
+        /* pending credits are not part of current before the roll over */
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 2, 10));
+        assert!(Token::verify_disclosure(&2, b"audit", &exact));
+    });
+}
+
+#[test]
+fn fee_is_checked_before_the_call() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 200, 5, 16, &Token::proof_context(&2, 0), &mut rng);
+        let proof = tagged(2, 0, range, &mut rng);
+        let call = Call::Token(crate::Call::shield(1));
+        /* below the base fee */
+        let fee = ChargeConfidentialFee::<Test, DefaultInstance>::from(0, proof.clone());
+        assert_eq!(fee.pre_dispatch(&2, &call, DispatchInfo::default(), 0), Err(InvalidTransaction::Payment.into()));
+        /* the proof is about a fee of 5 */
+        let fee = ChargeConfidentialFee::<Test, DefaultInstance>::from(6, proof.clone());
+        assert_eq!(fee.pre_dispatch(&2, &call, DispatchInfo::default(), 0), Err(InvalidTransaction::Payment.into()));
+        /* a SecondToken call pays its fee in SecondToken */
+        let other = Call::SecondToken(crate::Call::shield(1));
+        let fee = ChargeConfidentialFee::<Test, DefaultInstance>::from(5, proof);
+        assert_eq!(fee.pre_dispatch(&2, &other, DispatchInfo::default(), 0), Err(InvalidTransaction::Call.into()));
+        assert_eq!(Token::confidential_nonce(&2), 0);
+        assert!(decrypts(&TOKEN_CIPHER, secret(2), current(2), 200));
+    });
+}
+
+#[test]
+fn failed_calls_write_nothing_in_a_new_epoch() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        assert_ok!(Token::mint(RawOrigin::Root.into(), 2, 10));
+        System::set_block_number(EpochLength::get());
+        let balance = Token::balance_preview(&TOKEN_CIPHER, &2).unwrap();
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 210, 5, 16, &Token::proof_context(&2, 0), &mut rng);
+        let proof = tagged(2, 0, range, &mut rng);
+        /* the proof is about 5 */
+        let fee = ChargeConfidentialFee::<Test, DefaultInstance>::from(6, proof.clone());
+        let call = Call::Token(crate::Call::shield(1));
+        assert_noop!(fee.pre_dispatch(&2, &call, DispatchInfo::default(), 0), InvalidTransaction::Payment);
+        assert_noop!(Token::burn(RawOrigin::Root.into(), 2, 6, proof), TokenError::InvalidProof);
+        /* nothing is locked */
+        assert_noop!(Token::transfer(Origin::signed(2), 5, 1), TokenError::LockMismatch);
+        let equality = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_EQUALITY, 0, &0u128);
+        assert_noop!(Token::transfer_encrypted(Origin::signed(2), 1, (1, 1), equality), TokenError::LockMismatch);
+        assert!(Token::pending_state(&2).unwrap().due);
+    });
+}
+
+#[test]
+fn pool_drops_bad_proofs_and_orders_the_rest() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let range = prover::unshield(&TOKEN_CIPHER, secret(2), &balance, 200, 50, 16, &Token::proof_context(&2, 0), &mut rng);
+        let wrong = crate::Call::<Test, DefaultInstance>::unshield(60, tagged(2, 0, range.clone(), &mut rng));
+        assert_eq!(
+            Token::validate_call(&2, &wrong),
+            Err(InvalidTransaction::Custom(TokenError::InvalidProof.as_u8()).into()),
+        );
+        let valid = crate::Call::<Test, DefaultInstance>::unshield(50, tagged(2, 0, range, &mut rng));
+        assert_eq!(Token::validate_call(&2, &valid).unwrap().provides, vec![Token::nonce_tag(&2, 0)]);
+
+        /* bound to the next nonce, kept unchecked behind the call bound to this one */
+        let later = crate::Call::<Test, DefaultInstance>::unshield(50, prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_TAGGED_RANGE, 1, &0u128));
+        let kept = Token::validate_call(&2, &later).unwrap();
+        assert_eq!(kept.requires, vec![Token::nonce_tag(&2, 0)]);
+        assert_eq!(kept.provides, vec![Token::nonce_tag(&2, 1)]);
+    });
+}
+
+#[test]
+fn verify_call_names_the_failing_statement() {
+    new_test_ext().execute_with(|| {
+        let mut rng = rng();
+        let balance = <BalanceMap<Test, DefaultInstance>>::get(&2);
+        let dest_key = <BalanceMap<Test, DefaultInstance>>::get(&1).pubkey;
+        let (debit, credit, equality) = prover::confidential_transfer(
+            &TOKEN_CIPHER, secret(2), &balance, 200, dest_key, 80, 16, &Token::proof_context(&2, 0), &mut rng,
+        );
+        let proof = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_SIGMA_RANGE, 0, &debit.proof);
+        let remain_proof = tagged(2, 0, debit.remain_proof, &mut rng);
+        let equality = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_EQUALITY, 0, &equality);
+        let valid = crate::Call::<Test, DefaultInstance>::confidential_transfer(
+            1, debit.delta, credit, proof.clone(), remain_proof.clone(), equality.clone(),
+        );
+        assert_eq!(Token::verify_call(&2, &valid), Ok(()));
+
+        let other = TOKEN_CIPHER.encode(dest_key, 81, 5);
+        let wrong = crate::Call::<Test, DefaultInstance>::confidential_transfer(1, debit.delta, other, proof, remain_proof, equality);
+        assert_eq!(
+            Token::verify_call(&2, &wrong),
+            Err(ProofFailure::Failed { statement: Statement::Equality, error: b"InvalidProof".to_vec() }),
+        );
+        /* a dry run writes nothing */
+        assert_eq!(Token::confidential_nonce(&2), 0);
+    });
+}
+
+#[test]
+fn unsigned_transfers_wait_their_interval() {
+    new_test_ext().execute_with(|| {
+        let junk = prover::seal(&TOKEN_CIPHER, TOKEN_H, SCHEME_EQUALITY, 0, &0u128);
+        <LastUnsigned<Test, DefaultInstance>>::insert(1, 0);
+        System::set_block_number(9);
+        let call = crate::Call::<Test, DefaultInstance>::unsigned_transfer(
+            1, 2, (1, 1), (1, 1), junk.clone(), junk.clone(), junk.clone(), junk.clone(),
+        );
+        assert_eq!(
+            <Token as ValidateUnsigned>::validate_unsigned(&call),
+            Err(InvalidTransaction::Custom(TokenError::UnsignedTooSoon.as_u8()).into()),
+        );
+        assert_noop!(
+            Token::unsigned_transfer(RawOrigin::None.into(), 1, 2, (1, 1), (1, 1), junk.clone(), junk.clone(), junk.clone(), junk.clone()),
+            TokenError::UnsignedTooSoon,
+        );
+        assert_noop!(
+            Token::unsigned_transfer(Origin::signed(1), 1, 2, (1, 1), (1, 1), junk.clone(), junk.clone(), junk.clone(), junk),
+            DispatchError::BadOrigin,
+        );
+        /* nothing else goes unsigned */
+        let shield = crate::Call::<Test, DefaultInstance>::shield(1);
+        assert_eq!(<Token as ValidateUnsigned>::validate_unsigned(&shield), Err(InvalidTransaction::Call.into()));
+    });
+}
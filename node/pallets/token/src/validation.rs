@@ -0,0 +1,284 @@
+use codec::Encode;
+use frame_support::{
+    dispatch::Vec,
+    traits::Get,
+    unsigned::ValidateUnsigned,
+};
+use sp_runtime::{
+    traits::{StaticLookup, SaturatedConversion, Zero},
+    transaction_validity::{
+        TransactionValidity,
+        TransactionValidityError,
+        TransactionLongevity,
+        InvalidTransaction,
+        ValidTransaction,
+    },
+};
+
+use crate::*;
+
+//
+// Checks of token calls in the transaction pool.
+//
+// A call whose proofs are bound to the sender's current confidential
+// nonce n is verified against the balance it sees once included in the
+// next block, so invalid proofs are dropped before they take block space.
+// It provides the tag (instance, who, n). A call bound to a later nonce
+// can only be checked once its predecessors applied, it is kept without
+// verification and requires the tag of the nonce before its own.
+//
+// A check stays valid until the epoch ends, the next roll over changes
+// the balances the proofs were built against.
+//
+// Unsigned transfers pay no fee, so nothing but their proofs stops
+// anyone from flooding the pool with them. Each is checked in full,
+// one bound to a later nonce is rejected rather than kept unchecked,
+// it gets the runtime's UnsignedPriority and an account can send one
+// every UnsignedInterval blocks. The block author checks it in full once
+// more before including it, a failing one never takes block space.
+//
+
+/* Why a call is dropped from the pool, token errors travel as custom codes */
+pub(crate) fn pool_error<T: Trait<I>, I: Instance>(error: Error<T,I>) -> TransactionValidityError {
+    match error {
+        Error::<T,I>::StaleNonce => InvalidTransaction::Stale.into(),
+        Error::<T,I>::FutureNonce => InvalidTransaction::Future.into(),
+        error => InvalidTransaction::Custom(error.as_u8()).into(),
+    }
+}
+
+impl<T: Trait<I>, I: Instance> Module<T,I> {
+    /* Tag of who's confidential nonce, distinct for every token instance */
+    pub(crate) fn nonce_tag(who: &T::AccountId, nonce: u64) -> Vec<u8> {
+        (I::PREFIX, who, nonce).encode()
+    }
+
+    /* Blocks left in the epoch of the block a call in the pool goes into */
+    pub(crate) fn proof_longevity() -> TransactionLongevity {
+        let length = T::EpochLength::get();
+        if length.is_zero() {
+            return TransactionLongevity::max_value();
+        }
+        let now = <system::Module<T>>::block_number();
+        (length - now % length).saturated_into()
+    }
+
+    /* The envelope whose nonce orders a signed call, None if it carries no proof */
+    pub(crate) fn call_envelope(call: &Call<T,I>) -> Option<&ProofEnvelope> {
+        match call {
+            Call::lock_balance(_, proof) => Some(proof),
+            Call::lock_encrypted(_, proof, _) => Some(proof),
+            Call::transfer_encrypted(_, _, proof) => Some(proof),
+            Call::confidential_transfer(_, _, _, proof, _, _) => Some(proof),
+            Call::anonymous_transfer(_, transfer) => Some(transfer),
+            Call::unshield(_, proof) => Some(proof),
+            Call::migrate_balance(_, _, proof) => Some(proof),
+            Call::rotate_key(_, _, proof) => Some(proof),
+            _ => None,
+        }
+    }
+
+    /**
+     * Pool check of a token call signed by who, against who's state at
+     * the next block. The runtime calls it from its fee extension for
+     * calls whose fee is paid publicly.
+     */
+    pub fn validate_call(who: &T::AccountId, call: &Call<T,I>) -> TransactionValidity {
+        if Self::call_envelope(call).is_none() {
+            return Ok(ValidTransaction::default());
+        }
+        /* checked in the block, it only happens within a migration window */
+        if let Call::migrate_balance(..) = call {
+            return Ok(ValidTransaction::default());
+        }
+        let cipher = Self::cipher().map_err(pool_error)?;
+        let balance = Self::balance_preview(&cipher, who);
+        Self::validate_from(&cipher, who, Self::confidential_nonce(who), balance, call)
+    }
+
+    /**
+     * Check call as if who's nonce and balance were those given,
+     * ChargeConfidentialFee passes the state its fee leaves behind.
+     */
+    pub(crate) fn validate_from(
+        cipher: &EGICipher<u128>,
+        who: &T::AccountId,
+        nonce: u64,
+        balance: Option<CipherText<u128>>,
+        call: &Call<T,I>,
+    ) -> TransactionValidity {
+        let envelope = match Self::call_envelope(call) {
+            Some(envelope) => envelope,
+            None => return Ok(ValidTransaction::default()),
+        };
+        let (_, bound, _) = envelope.unseal(&Self::params_hash(cipher))
+            .map_err(|e| pool_error(e.into()))?;
+        if bound > nonce {
+            return Ok(ValidTransaction {
+                requires: vec![Self::nonce_tag(who, bound - 1)],
+                provides: vec![Self::nonce_tag(who, bound)],
+                longevity: Self::proof_longevity(),
+                ..Default::default()
+            });
+        }
+        Self::check_call(cipher, who, nonce, balance, call).map_err(|(_, e)| pool_error(e))?;
+        Ok(ValidTransaction {
+            provides: vec![Self::nonce_tag(who, nonce)],
+            longevity: Self::proof_longevity(),
+            ..Default::default()
+        })
+    }
+
+    /**
+     * The checks the call makes before it writes anything, nothing written.
+     * A failure names the statement it is about, for the dry run.
+     */
+    pub(crate) fn check_call(
+        cipher: &EGICipher<u128>,
+        who: &T::AccountId,
+        nonce: u64,
+        balance: Option<CipherText<u128>>,
+        call: &Call<T,I>,
+    ) -> Result<(), (Statement, Error<T,I>)> {
+        let about = |statement| move |error| (statement, error);
+        let sender = || balance.clone().ok_or((Statement::Call, Error::<T,I>::UnknownAccount));
+        let receiver = |recv: &<T::Lookup as StaticLookup>::Source| {
+            let dest = T::Lookup::lookup(recv.clone()).map_err(|_| (Statement::Call, Error::<T,I>::UnknownAccount))?;
+            Self::settled_balance(&dest).map(|balance| (dest, balance)).ok_or((Statement::Call, Error::<T,I>::UnknownAccount))
+        };
+        match call {
+            Call::lock_balance(amount, proof) => {
+                let balance = sender()?;
+                if <LockQueue<T,I>>::get().iter().any(|(queued, _, _, _)| queued == who) {
+                    return Err((Statement::Call, Error::<T,I>::LockQueued));
+                }
+                let amount = Self::check_amount(*amount).map_err(about(Statement::Call))?;
+                let delta = cipher.encode(balance.pubkey, amount, balance.rel);
+                Self::verify_remain(cipher, who, nonce, balance.pubkey, cipher.minus(balance.current, delta), proof)
+                    .map_err(about(Statement::Remain))
+            },
+            Call::lock_encrypted(delta, proof, remain_proof) => {
+                let balance = sender()?;
+                Self::verify_range(cipher, who, nonce, balance.pubkey, *delta, proof)
+                    .map_err(about(Statement::Debit))?;
+                Self::verify_remain(cipher, who, nonce, balance.pubkey, cipher.minus(balance.current, *delta), remain_proof)
+                    .map_err(about(Statement::Remain))
+            },
+            Call::transfer_encrypted(recv, credit, proof) => {
+                let balance = sender()?;
+                let (_, dest_balance) = receiver(recv)?;
+                let (_, released) = balance.clone().release_encrypted(cipher)
+                    .map_err(|_| (Statement::Call, Error::<T,I>::LockMismatch))?;
+                Self::verify_equality(cipher, who, nonce, balance.pubkey, released, dest_balance.pubkey, *credit, proof)
+                    .map_err(about(Statement::Equality))
+            },
+            Call::confidential_transfer(recv, debit, credit, proof, remain_proof, eq_proof) => {
+                let balance = sender()?;
+                let (dest, dest_balance) = receiver(recv)?;
+                if dest == *who {
+                    return Err((Statement::Call, Error::<T,I>::TransferToSelf));
+                }
+                Self::verify_range(cipher, who, nonce, balance.pubkey, *debit, proof)
+                    .map_err(about(Statement::Debit))?;
+                Self::verify_remain(cipher, who, nonce, balance.pubkey, cipher.minus(balance.current, *debit), remain_proof)
+                    .map_err(about(Statement::Remain))?;
+                Self::verify_equality(cipher, who, nonce, balance.pubkey, *debit, dest_balance.pubkey, *credit, eq_proof)
+                    .map_err(about(Statement::Equality))
+            },
+            Call::anonymous_transfer(ring, transfer) => {
+                let transfer = Self::open_ring(cipher, nonce, transfer).map_err(about(Statement::Ring))?;
+                Self::check_tag(transfer.tag).map_err(about(Statement::Ring))?;
+                if ring.len() != T::RingSize::get() as usize {
+                    return Err((Statement::Call, Error::<T,I>::InvalidRing));
+                }
+                let mut members = Vec::new();
+                let mut balances = Vec::new();
+                for source in ring {
+                    let member = T::Lookup::lookup(source.clone()).map_err(|_| (Statement::Call, Error::<T,I>::UnknownAccount))?;
+                    if members.contains(&member) {
+                        return Err((Statement::Call, Error::<T,I>::InvalidRing));
+                    }
+                    balances.push(Self::balance_preview(cipher, &member).ok_or((Statement::Call, Error::<T,I>::UnknownAccount))?);
+                    members.push(member);
+                }
+                let keys = balances.iter().map(|b| b.pubkey).collect::<Vec<_>>();
+                let currents = balances.iter().map(|b| b.current).collect::<Vec<_>>();
+                let context = Self::proof_context(who, nonce);
+                let base = Self::epoch_generator(cipher);
+                if cipher.anonymous_transfer(SecondGenerator::<I>::get(), base, &keys, &currents, Self::range_width(), &context, &transfer) {
+                    Ok(())
+                } else {
+                    Err((Statement::Ring, Error::<T,I>::InvalidProof))
+                }
+            },
+            Call::unsigned_transfer(_, recv, debit, credit, proof, remain_proof, eq_proof, authorisation) => {
+                let key = sender()?.pubkey;
+                let (dest, _) = receiver(recv)?;
+                let context = Self::authorisation_context(
+                    who, nonce, &dest, *debit, *credit, proof, remain_proof, eq_proof,
+                );
+                Self::verify_authorisation(cipher, nonce, key, &context, authorisation)
+                    .map_err(about(Statement::Ownership))?;
+                let transfer = Call::confidential_transfer(
+                    recv.clone(), *debit, *credit, proof.clone(), remain_proof.clone(), eq_proof.clone(),
+                );
+                Self::check_call(cipher, who, nonce, balance, &transfer)
+            },
+            Call::unshield(amount, proof) => {
+                let balance = sender()?;
+                let value = Self::confidential_amount(*amount).map_err(about(Statement::Call))?;
+                let debit = cipher.encode(balance.pubkey, value, 0);
+                Self::verify_remain(cipher, who, nonce, balance.pubkey, cipher.minus(balance.current, debit), proof)
+                    .map_err(about(Statement::Remain))
+            },
+            Call::rotate_key(key, current, proof) => {
+                let balance = sender()?;
+                Self::check_rotation(cipher, who, nonce, &balance, *key, *current, proof)
+                    .map_err(about(Statement::Equality))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T: Trait<I>, I: Instance> Module<T,I> {
+    /* Interval and proofs of an unsigned_transfer, who sends it and the nonce its proofs are bound to */
+    fn check_unsigned(call: &Call<T,I>) -> Result<(T::AccountId, u64), TransactionValidityError> {
+        let src = match call {
+            Call::unsigned_transfer(src, ..) => src,
+            _ => return Err(InvalidTransaction::Call.into()),
+        };
+        let who = T::Lookup::lookup(src.clone()).map_err(|_| pool_error(Error::<T,I>::UnknownAccount))?;
+        Self::check_interval(&who).map_err(pool_error)?;
+        let cipher = Self::cipher().map_err(pool_error)?;
+        let nonce = Self::confidential_nonce(&who);
+        let balance = Self::balance_preview(&cipher, &who);
+        Self::check_call(&cipher, &who, nonce, balance, call).map_err(|(_, e)| pool_error(e))?;
+        Ok((who, nonce))
+    }
+}
+
+impl<T: Trait<I>, I: Instance> ValidateUnsigned for Module<T,I> {
+    type Call = Call<T,I>;
+
+    /* Only unsigned_transfer, checked from the sender's state at the next block */
+    fn validate_unsigned(call: &Self::Call) -> TransactionValidity {
+        let (who, nonce) = Self::check_unsigned(call)?;
+        Ok(ValidTransaction {
+            priority: T::UnsignedPriority::get(),
+            requires: Vec::new(),
+            provides: vec![Self::nonce_tag(&who, nonce)],
+            longevity: Self::proof_longevity().min(T::UnsignedInterval::get().saturated_into()),
+            propagate: true,
+        })
+    }
+
+    /**
+     * A failing unsigned call would still take block space and pay
+     * nothing for it, so its proofs are checked again against the state
+     * of the block before it goes in.
+     */
+    fn pre_dispatch(call: &Self::Call) -> Result<(), TransactionValidityError> {
+        Self::check_unsigned(call).map(|_| ())
+    }
+}
@@ -0,0 +1,116 @@
+use frame_support::weights::Weight;
+
+//
+// Weights of the token extrinsics.
+//
+// Each weight has the form base + slope * parameter, bits being the width
+// of range proofs and the other parameters the sizes of the proofs. One
+// unit of weight is a nanosecond, MaximumBlockWeight of 10^9 is a second.
+// The proof constants are least squares fits of the verifiers run by the
+// benchmarks in benchmarking.rs, timed natively in a release build at
+// widths 8, 16 and 28 and with rings of 4 and 8 members. They are doubled
+// for the slower Wasm execution of the runtime. The storage constants
+// were not part of that timing and stay estimates. Callers pass
+// RangeBits, the widest width the runtime allows, so a narrower genesis
+// width only overpays.
+//
+
+/* Margin for the runtime running in Wasm, the fits below are native */
+const WASM: Weight = 2;
+
+/* Reading and writing the balances of an extrinsic with proofs */
+const PROOF_BASE: Weight = 10_000;
+/* Queueing a lock, the extrinsic only checks the shape of its proof */
+const LOCK_QUEUE: Weight = 10_000;
+/* Share of one bit of a range proof in the batch check of on_finalize,
+ * with the lock's tag. The batch saves the exponentiations of the
+ * check, not the membership tests of the proof's elements. */
+const BATCH_BASE: Weight = 46_000 * WASM;
+const BATCH_BIT: Weight = 161_000 * WASM;
+/* Checking a range proof on its own, per proof and per bit */
+const RANGE_BASE: Weight = 166_000 * WASM;
+const RANGE_BIT: Weight = 195_000 * WASM;
+const EQUALITY: Weight = 176_000 * WASM;
+/* The proof of the epoch tag, with every proof spending from current */
+const TAG: Weight = 82_000 * WASM;
+/* The proof of the secret key authorising an unsigned call */
+const OWNERSHIP: Weight = 30_000 * WASM;
+/* The one-out-of-many part of a ring proof, per member */
+const RING_MEMBER: Weight = 414_000 * WASM;
+/* Primality of p and (p - 1) / 2 and the order of γ and h, for the widest p */
+const PARAMS: Weight = 1_800_000 * WASM;
+
+/* Checking count range proofs of width bits one by one */
+fn range(count: Weight, bits: u32) -> Weight {
+    count * (RANGE_BASE + RANGE_BIT * bits as Weight)
+}
+
+pub fn transfer() -> Weight {
+    PROOF_BASE
+}
+
+/* Paid up front for on_finalize: the lock's share of the batch check, and
+ * checking its proof on its own when the batch fails */
+pub fn lock_balance(bits: u32) -> Weight {
+    LOCK_QUEUE + BATCH_BASE + BATCH_BIT * bits as Weight + range(1, bits) + TAG
+}
+
+pub fn lock_encrypted(bits: u32) -> Weight {
+    PROOF_BASE + range(2, bits) + TAG
+}
+
+pub fn transfer_encrypted() -> Weight {
+    PROOF_BASE + EQUALITY
+}
+
+pub fn confidential_transfer(bits: u32) -> Weight {
+    PROOF_BASE + EQUALITY + range(2, bits) + TAG
+}
+
+pub fn unsigned_transfer(bits: u32) -> Weight {
+    confidential_transfer(bits) + OWNERSHIP
+}
+
+/* A range proof per member and one for the amount */
+pub fn anonymous_transfer(ring: u32, bits: u32) -> Weight {
+    let ring = ring as Weight;
+    PROOF_BASE * ring + RING_MEMBER * ring + range(ring + 1, bits) + TAG
+}
+
+pub fn shield() -> Weight {
+    PROOF_BASE
+}
+
+pub fn unshield(bits: u32) -> Weight {
+    PROOF_BASE + range(1, bits) + TAG
+}
+
+pub fn mint() -> Weight {
+    PROOF_BASE
+}
+
+pub fn burn(bits: u32) -> Weight {
+    PROOF_BASE + range(1, bits) + TAG
+}
+
+pub fn reset_balance() -> Weight {
+    PROOF_BASE
+}
+
+pub fn set_pubkey() -> Weight {
+    PROOF_BASE
+}
+
+/* Every bit is proved under both keys */
+pub fn rotate_key(bits: u32) -> Weight {
+    PROOF_BASE + range(2, bits)
+}
+
+pub fn set_cipher_params() -> Weight {
+    PARAMS
+}
+
+/* Every bit is proved in both groups */
+pub fn migrate_balance(bits: u32) -> Weight {
+    PROOF_BASE + range(2, bits)
+}
@@ -12,11 +12,15 @@ use sp_std::prelude::*;
 use sp_core::OpaqueMetadata;
 use sp_runtime::{
 	ApplyExtrinsicResult, transaction_validity::TransactionValidity, generic, create_runtime_str,
-	impl_opaque_keys, MultiSignature,
+	impl_opaque_keys, MultiSignature, RuntimeDebug,
 };
 use sp_runtime::traits::{
-	BlakeTwo256, Block as BlockT, IdentityLookup, Verify, ConvertInto, IdentifyAccount
+	BlakeTwo256, Block as BlockT, IdentityLookup, Verify, ConvertInto, IdentifyAccount,
+	Convert, SignedExtension,
 };
+use sp_runtime::transaction_validity::{TransactionValidityError, TransactionPriority};
+use frame_support::weights::DispatchInfo;
+use codec::{Encode, Decode};
 use sp_api::impl_runtime_apis;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use grandpa::AuthorityList as GrandpaAuthorityList;
@@ -97,7 +101,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("node-template"),
 	impl_name: create_runtime_str!("node-template"),
 	authoring_version: 1,
-	spec_version: 1,
+	spec_version: 2,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
 };
@@ -218,9 +222,55 @@ impl transaction_payment::Trait for Runtime {
 	type FeeMultiplierUpdate = ();
 }
 
+parameter_types! {
+	pub const EpochLength: BlockNumber = 10;
+	pub const RingSize: u32 = 4;
+	/// Amounts below 2^16, the group order (p - 1) / 2 of the testing prime allows up to 28.
+	pub const RangeBits: u32 = 16;
+	pub const ConfidentialBaseFee: u128 = 1;
+	pub const ConfidentialByteFee: u128 = 0;
+	/// Below anything that pays a fee.
+	pub const UnsignedPriority: TransactionPriority = 0;
+	pub const UnsignedInterval: BlockNumber = 10;
+}
+
+/// One unit of confidential fee per 10_000_000 of weight, 10ms of execution.
+pub struct WeightToConfidentialFee;
+
+impl Convert<Weight, u128> for WeightToConfidentialFee {
+	fn convert(weight: Weight) -> u128 {
+		weight as u128 / 10_000_000
+	}
+}
+
 impl token::Trait for Runtime {
     type Balance = token::CipherText<u128>;
     type Event = Event;
+    type EpochLength = EpochLength;
+    type RingSize = RingSize;
+    type RangeBits = RangeBits;
+    type Currency = Balances;
+    type ConfidentialBaseFee = ConfidentialBaseFee;
+    type ConfidentialByteFee = ConfidentialByteFee;
+    type WeightToConfidentialFee = WeightToConfidentialFee;
+    type UnsignedPriority = UnsignedPriority;
+    type UnsignedInterval = UnsignedInterval;
+}
+
+/// A second confidential token, with its own genesis and storage. It shields
+/// from the same public currency.
+impl token::Trait<token::Instance1> for Runtime {
+    type Balance = token::CipherText<u128>;
+    type Event = Event;
+    type EpochLength = EpochLength;
+    type RingSize = RingSize;
+    type RangeBits = RangeBits;
+    type Currency = Balances;
+    type ConfidentialBaseFee = ConfidentialBaseFee;
+    type ConfidentialByteFee = ConfidentialByteFee;
+    type WeightToConfidentialFee = WeightToConfidentialFee;
+    type UnsignedPriority = UnsignedPriority;
+    type UnsignedInterval = UnsignedInterval;
 }
 
 impl sudo::Trait for Runtime {
@@ -247,7 +297,8 @@ construct_runtime!(
 		Balances: balances::{Module, Call, Storage, Config<T>, Event<T>},
 		TransactionPayment: transaction_payment::{Module, Storage},
 		Sudo: sudo::{Module, Call, Config<T>, Storage, Event<T>},
-        Token: token:: {Module, Call, Storage, Event<T>, Config},
+        Token: token:: {Module, Call, Storage, Event<T>, Config<T>, ValidateUnsigned},
+        SecondToken: token::<Instance1>::{Module, Call, Storage, Event<T>, Config<T>, ValidateUnsigned},
 		// Used for the module template in `./template.rs`
 		TemplateModule: template::{Module, Call, Storage, Event<T>},
 	}
@@ -270,12 +321,103 @@ pub type SignedExtra = (
 	system::CheckEra<Runtime>,
 	system::CheckNonce<Runtime>,
 	system::CheckWeight<Runtime>,
-	transaction_payment::ChargeTransactionPayment<Runtime>
+	ChargeFee
 );
+
+/// Fee payment of a signed extrinsic. Token calls may pay from the sender's
+/// confidential balance, so that they need no public account. Everything else,
+/// and token calls of accounts without a confidential balance yet, pay publicly.
+/// Calls of either token pay their confidential fee in that token.
+#[derive(Encode, Decode, Clone, Eq, PartialEq, RuntimeDebug)]
+pub enum ChargeFee {
+	Public(transaction_payment::ChargeTransactionPayment<Runtime>),
+	Confidential(token::ChargeConfidentialFee<Runtime>),
+	SecondConfidential(token::ChargeConfidentialFee<Runtime, token::Instance1>),
+}
+
+impl SignedExtension for ChargeFee {
+	const IDENTIFIER: &'static str = "ChargeFee";
+	type AccountId = AccountId;
+	type Call = Call;
+	type AdditionalSigned = ();
+	type DispatchInfo = DispatchInfo;
+	type Pre = ();
+
+	fn additional_signed(&self) -> Result<(), TransactionValidityError> { Ok(()) }
+
+	/// Token proofs are checked here as well, so that the pool drops calls
+	/// whose proofs fail and orders those of one account by their nonce.
+	fn validate(
+		&self,
+		who: &AccountId,
+		call: &Call,
+		info: DispatchInfo,
+		len: usize,
+	) -> TransactionValidity {
+		match self {
+			ChargeFee::Public(charge) => {
+				let paid = charge.validate(who, call, info, len)?;
+				match call {
+					Call::Token(call) => Ok(paid.combine_with(Token::validate_call(who, call)?)),
+					Call::SecondToken(call) => Ok(paid.combine_with(SecondToken::validate_call(who, call)?)),
+					_ => Ok(paid),
+				}
+			},
+			ChargeFee::Confidential(charge) => charge.validate(who, call, info, len),
+			ChargeFee::SecondConfidential(charge) => charge.validate(who, call, info, len),
+		}
+	}
+
+	fn pre_dispatch(
+		self,
+		who: &AccountId,
+		call: &Call,
+		info: DispatchInfo,
+		len: usize,
+	) -> Result<(), TransactionValidityError> {
+		match self {
+			ChargeFee::Public(charge) => charge.pre_dispatch(who, call, info, len).map(|_| ()),
+			ChargeFee::Confidential(charge) => charge.pre_dispatch(who, call, info, len),
+			ChargeFee::SecondConfidential(charge) => charge.pre_dispatch(who, call, info, len),
+		}
+	}
+}
 /// Unchecked extrinsic type as expected by this runtime.
 pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signature, SignedExtra>;
 /// Extrinsic type that has already been checked.
 pub type CheckedExtrinsic = generic::CheckedExtrinsic<AccountId, Call, SignedExtra>;
+/// Evaluates `$f` with `$module` standing for the token module named `$name`,
+/// None if the runtime has no token of that name.
+macro_rules! with_token {
+	($name:expr, |$module:ident| $f:expr) => {
+		match &$name[..] {
+			b"Token" => {
+				type $module = Token;
+				Some($f)
+			},
+			b"SecondToken" => {
+				type $module = SecondToken;
+				Some($f)
+			},
+			_ => None,
+		}
+	};
+}
+
+/// The events of this block that `select` picks as those of a token, each
+/// with the index of the extrinsic that emitted it.
+fn token_events<I: token::Instance>(
+	select: impl Fn(Event) -> Option<token::Event<Runtime, I>>,
+) -> impl Iterator<Item = (Option<u32>, token::Event<Runtime, I>)> {
+	System::events().into_iter().filter_map(move |record| {
+		let extrinsic = match record.phase {
+			system::Phase::ApplyExtrinsic(index) => Some(index),
+			_ => None,
+		};
+		select(record.event).map(|event| (extrinsic, event))
+	})
+}
+
 /// Executive: handles dispatch to the various modules.
 pub type Executive = frame_executive::Executive<Runtime, Block, system::ChainContext<Runtime>, Runtime, AllModules>;
 
@@ -364,4 +506,84 @@ impl_runtime_apis! {
 			Grandpa::grandpa_authorities()
 		}
 	}
+
+	impl token_runtime_api::TokenAuditApi<Block, AccountId> for Runtime {
+		fn disclosure_context(token: Vec<u8>, who: AccountId, challenge: Vec<u8>) -> Option<Vec<u8>> {
+			with_token!(token, |Module| Module::disclosure_context(&who, &challenge))
+		}
+
+		fn verify_disclosure(
+			token: Vec<u8>,
+			who: AccountId,
+			challenge: Vec<u8>,
+			disclosure: token::Disclosure<u128>,
+		) -> Option<bool> {
+			with_token!(token, |Module| Module::verify_disclosure(&who, &challenge, &disclosure))
+		}
+	}
+
+	impl token_runtime_api::TokenApi<Block, AccountId, BlockNumber> for Runtime {
+		fn balance(token: Vec<u8>, who: AccountId) -> Option<Option<token::AccountBalance>> {
+			with_token!(token, |Module| Module::account_balance(&who))
+		}
+
+		fn cipher_params(token: Vec<u8>) -> Option<token::CipherParams<BlockNumber>> {
+			with_token!(token, |Module| Module::cipher_params())
+		}
+
+		fn range_width(token: Vec<u8>) -> Option<u32> {
+			with_token!(token, |Module| Module::proof_width())
+		}
+
+		fn pending(token: Vec<u8>, who: AccountId) -> Option<Option<token::PendingState<BlockNumber>>> {
+			with_token!(token, |Module| Module::pending_state(&who))
+		}
+
+		fn verify_call(token: Vec<u8>, who: AccountId, call: Vec<u8>) -> Option<Result<(), token::ProofFailure>> {
+			with_token!(token, |Module| match Decode::decode(&mut &call[..]) {
+				Ok(call) => Module::verify_call(&who, &call),
+				Err(_) => Err(token::ProofFailure::BadCall),
+			})
+		}
+
+		fn incoming(
+			token: Vec<u8>,
+			who: Option<AccountId>,
+			pubkey: Option<token::BigInt>,
+		) -> Option<Vec<token::Incoming<AccountId>>> {
+			let key = pubkey.map(Into::into);
+			match &token[..] {
+				b"Token" => Some(Token::incoming(
+					token_events(|event| match event { Event::token(event) => Some(event), _ => None }),
+					who.as_ref(),
+					key,
+				)),
+				b"SecondToken" => Some(SecondToken::incoming(
+					token_events(|event| match event { Event::token_Instance1(event) => Some(event), _ => None }),
+					who.as_ref(),
+					key,
+				)),
+				_ => None,
+			}
+		}
+	}
+
+	impl frame_benchmarking::Benchmark<Block> for Runtime {
+		fn dispatch_benchmark(
+			module: Vec<u8>,
+			extrinsic: Vec<u8>,
+			steps: Vec<u32>,
+			repeat: u32,
+		) -> Option<Vec<frame_benchmarking::BenchmarkResults>> {
+			#[cfg(feature = "runtime-benchmarks")]
+			use frame_benchmarking::Benchmarking;
+
+			/* Only runtimes built with runtime-benchmarks carry the benchmarks */
+			match module.as_slice() {
+				#[cfg(feature = "runtime-benchmarks")]
+				b"pallet-token" | b"token" => Token::run_benchmark(extrinsic, steps, repeat).ok(),
+				_ => None,
+			}
+		}
+	}
 }